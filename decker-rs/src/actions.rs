@@ -2,46 +2,58 @@ use std::collections::BTreeMap;
 use std::rc::Rc;
 
 use crate::collections::{CardCollectionPtr, CollectionIterator};
-use crate::properties::CardGroupProperty;
+use crate::constraints::{extra_note, extra_tag};
+use crate::errors::{DeckerError, DeckerResult};
+use crate::interactions::Interaction;
+use crate::properties::{missing_type_piles, CardGroupProperty};
 use crate::selections::SelectionPtr;
 
 trait ConstraintAction {
     // send back a selection or an error message
-    fn apply(&self, label: &str, start: &SelectionPtr) -> Result<SelectionPtr, String>;
+    fn apply(&self, label: &str, start: &SelectionPtr) -> DeckerResult<SelectionPtr>;
 }
 
 pub struct ConstraintActionPtr(Rc<dyn ConstraintAction>);
 
 impl ConstraintActionPtr {
-    pub fn apply(&self, label: &str, start: &SelectionPtr) -> Result<SelectionPtr, String> {
+    pub fn apply(&self, label: &str, start: &SelectionPtr) -> DeckerResult<SelectionPtr> {
         self.0.apply(label, start)
     }
 }
 
+// Adds an extra kingdom pile (eg Young Witch's bane) on top of the usual 10
 pub struct FindBane {
     begin: CollectionIterator,
     col: CardCollectionPtr,
+    tag: String,
+    note: String,
 }
 
 impl FindBane {
-    pub fn make_ptr(col: &CardCollectionPtr, begin_it: &CollectionIterator) -> ConstraintActionPtr {
+    pub fn make_ptr(
+        col: &CardCollectionPtr,
+        begin_it: &CollectionIterator,
+        extra_name: &str,
+    ) -> ConstraintActionPtr {
         ConstraintActionPtr(Rc::new(FindBane {
             begin: begin_it.clone(),
             col: col.clone(),
+            tag: extra_tag(extra_name),
+            note: extra_note(extra_name),
         }))
     }
 }
 
 impl ConstraintAction for FindBane {
-    fn apply(&self, label: &str, start: &SelectionPtr) -> Result<SelectionPtr, String> {
+    fn apply(&self, label: &str, start: &SelectionPtr) -> DeckerResult<SelectionPtr> {
         for it in &mut self.begin.clone() {
             if !start.contains(&it) {
                 let mut new_sel = start.duplicate_state();
                 new_sel.increase_required_piles();
                 if new_sel.add_pile(&it) {
-                    new_sel.tag_pile(&it, &"Bane".to_string());
+                    new_sel.tag_pile(&it, &self.tag);
                     new_sel.tag_pile(&it, &format!("<why:{}>", label));
-                    new_sel.add_note(&"hasBane".to_string());
+                    new_sel.add_note(&self.note);
                     let res = self.col.build_selection(&SelectionPtr::from_state(new_sel));
                     if res.is_ok() {
                         return res;
@@ -49,7 +61,10 @@ impl ConstraintAction for FindBane {
                 }
             }
         }
-        Err("".to_string())
+        Err(DeckerError::SearchExhausted(format!(
+            "No pile could be added for {}",
+            label
+        )))
     }
 }
 
@@ -68,16 +83,16 @@ impl AddGroup {
 }
 
 impl ConstraintAction for AddGroup {
-    fn apply(&self, label: &str, start: &SelectionPtr) -> Result<SelectionPtr, String> {
+    fn apply(&self, label: &str, start: &SelectionPtr) -> DeckerResult<SelectionPtr> {
         let mut new_sel = start.duplicate_state();
         let p = CardGroupProperty::make_ptr(&self.group);
         let begin = match self.coll.get_iterators(&p) {
             Some(v) => v,
             None => {
-                return Err(format!(
+                return Err(DeckerError::Unsatisfiable(format!(
                     "Tried to add group ({}) but no cards belonging to it found in the collection.",
                     self.group
-                ))
+                )))
             }
         };
         for it in begin {
@@ -108,7 +123,7 @@ impl FindPile {
 }
 
 impl ConstraintAction for FindPile {
-    fn apply(&self, label: &str, start: &SelectionPtr) -> Result<SelectionPtr, String> {
+    fn apply(&self, label: &str, start: &SelectionPtr) -> DeckerResult<SelectionPtr> {
         for it in self.begin.clone() {
             if !start.contains(&it) {
                 let mut new_sel = start.duplicate_state();
@@ -120,7 +135,10 @@ impl ConstraintAction for FindPile {
                 }
             };
         }
-        Err("".to_string())
+        Err(DeckerError::SearchExhausted(format!(
+            "No pile could be added for {}",
+            label
+        )))
     }
 }
 
@@ -135,30 +153,31 @@ impl AddMissingDependency {
 }
 
 impl ConstraintAction for AddMissingDependency {
-    fn apply(&self, _label: &str, start: &SelectionPtr) -> Result<SelectionPtr, String> {
+    fn apply(&self, _label: &str, start: &SelectionPtr) -> DeckerResult<SelectionPtr> {
         let mut need = BTreeMap::<String, String>::new();
         for p in start.get_piles()
         // This code is duplicated in Missing...Property
         {
             for it in p.get_other_interactions() {
-                if it.starts_with("card(") {
-                    let prefix_len = "card(".len();
-                    let need_name = &it[prefix_len..it.len() - '('.len_utf8()];
+                if let Interaction::Card(need_name) = it {
                     need.insert(need_name.to_string(), p.get_name().to_string());
                 }
             }
         }
         if need.is_empty() {
-            return Err(
+            return Err(DeckerError::Unsatisfiable(
                 "AddMissingDependency applied but no cards have card() OtherInteractions"
                     .to_string(),
-            );
+            ));
         };
         for (first, second) in &need {
             let p = match self.col.get_pile_for_card(first) {
                 Some(v) => v,
                 None => {
-                    return Err(format!("Unable to find a pile containing {}", first));
+                    return Err(DeckerError::Unsatisfiable(format!(
+                        "Unable to find a pile containing {}",
+                        first
+                    )));
                 }
             };
             if !start.contains(&p) {
@@ -169,7 +188,88 @@ impl ConstraintAction for AddMissingDependency {
                 };
             };
         }
-        Err("AddMissingDependency applied but nothing seemed missing".to_string())
+        Err(DeckerError::Unsatisfiable(
+            "AddMissingDependency applied but nothing seemed missing".to_string(),
+        ))
+    }
+}
+
+// Adds whichever pile of a --require-together pair is missing
+pub struct AddPartner {
+    col: CardCollectionPtr,
+    first: String,
+    second: String,
+}
+
+impl AddPartner {
+    pub fn make_ptr(col: &CardCollectionPtr, first: &str, second: &str) -> ConstraintActionPtr {
+        ConstraintActionPtr(Rc::new(AddPartner {
+            col: col.clone(),
+            first: first.to_string(),
+            second: second.to_string(),
+        }))
+    }
+}
+
+impl ConstraintAction for AddPartner {
+    fn apply(&self, label: &str, start: &SelectionPtr) -> DeckerResult<SelectionPtr> {
+        let has = |name: &str| start.get_piles().iter().any(|p| p.get_name() == name);
+        let (have, need) = if has(&self.first) {
+            (&self.first, &self.second)
+        } else {
+            (&self.second, &self.first)
+        };
+        // the partner may not be in the collection (eg --groups)
+        let p = match self.col.get_piles().iter().find(|p| p.get_name() == need) {
+            Some(p) => p.clone(),
+            None => {
+                return Err(DeckerError::Unsatisfiable(format!(
+                    "{} needs {} which isn't in the collection",
+                    label, need
+                )))
+            }
+        };
+        let mut new_sel = start.duplicate_state();
+        if !new_sel.add_pile(&p) {
+            return Err(DeckerError::Unsatisfiable(format!(
+                "No room to add {} for {}",
+                need, label
+            )));
+        }
+        new_sel.tag_pile(&p, &format!("<why?pair:{} requires it>", have));
+        self.col.build_selection(&SelectionPtr::from_state(new_sel))
+    }
+}
+
+pub struct AddMissingTypeDependency {
+    col: CardCollectionPtr,
+}
+
+impl AddMissingTypeDependency {
+    pub fn make_ptr(col: &CardCollectionPtr) -> ConstraintActionPtr {
+        ConstraintActionPtr(Rc::new(AddMissingTypeDependency { col: col.clone() }))
+    }
+}
+
+impl ConstraintAction for AddMissingTypeDependency {
+    fn apply(&self, _label: &str, start: &SelectionPtr) -> DeckerResult<SelectionPtr> {
+        let need = missing_type_piles(start);
+        if need.is_empty() {
+            return Err(DeckerError::Unsatisfiable(
+                "AddMissingTypeDependency applied but nothing seemed missing".to_string(),
+            ));
+        }
+        let mut new_sel = start.duplicate_state();
+        for (p, cause) in &need {
+            if !new_sel.add_pile(p) {
+                return Err(DeckerError::Unsatisfiable(format!(
+                    "Unable to add card {}",
+                    p.get_name()
+                )));
+            }
+            new_sel.tag_pile(p, &format!("<why?type:{} needs it>", cause));
+        }
+        self.col.build_selection(&SelectionPtr::from_state(new_sel))
     }
 }
 
@@ -184,24 +284,22 @@ impl AddMissingDependencyGroup {
 }
 
 impl ConstraintAction for AddMissingDependencyGroup {
-    fn apply(&self, _label: &str, start: &SelectionPtr) -> Result<SelectionPtr, String> {
+    fn apply(&self, _label: &str, start: &SelectionPtr) -> DeckerResult<SelectionPtr> {
         // The c++ version only initialised when needed
         // but an Option seemed clunky
         let mut new_sel = start.duplicate_state();
         let mut acted = false;
         for p in start.get_piles() {
             for it in p.get_other_interactions() {
-                if it.starts_with("group(") {
-                    let prefix_len = "group(".len();
-                    let need_name = &it[prefix_len..it.len() - ')'.len_utf8()];
+                if let Interaction::Group(need_name) = it {
                     if !start.has_note(&format!("added{}", need_name)) {
                         let ps = CardGroupProperty::make_ptr(need_name);
                         let piles = match start.get_collection().get_iterators(&ps) {
                             None => {
-                                return Err(format!(
+                                return Err(DeckerError::Unsatisfiable(format!(
                                     "Unable to find required group named {}",
                                     need_name
-                                ))
+                                )))
                             }
                             Some(r) => r,
                         };
@@ -213,7 +311,10 @@ impl ConstraintAction for AddMissingDependencyGroup {
                                     &format!("<why?cards:{} needs it>", p.get_name()),
                                 );
                             } else {
-                                return Err(format!("Unable to add card {}", i.get_name()));
+                                return Err(DeckerError::Unsatisfiable(format!(
+                                    "Unable to add card {}",
+                                    i.get_name()
+                                )));
                             }
                         }
                         new_sel.add_note(&format!("added{}", need_name));
@@ -222,9 +323,9 @@ impl ConstraintAction for AddMissingDependencyGroup {
             }
         }
         if !acted {
-            return Err(
+            return Err(DeckerError::Unsatisfiable(
                 "AddMissingDependencyGroup called buit nothing seems to be missing".to_string(),
-            );
+            ));
         }
         self.col.build_selection(&SelectionPtr::from_state(new_sel))
     }
@@ -241,7 +342,7 @@ impl AddProsperity {
 }
 
 impl ConstraintAction for AddProsperity {
-    fn apply(&self, label: &str, start: &SelectionPtr) -> Result<SelectionPtr, String> {
+    fn apply(&self, label: &str, start: &SelectionPtr) -> DeckerResult<SelectionPtr> {
         // The c++ version only initialised when needed
         // but an Option seemed clunky
         let mut new_sel = start.duplicate_state();
@@ -250,24 +351,32 @@ impl ConstraintAction for AddProsperity {
         let platinum = match col.get_pile_for_card(&"Platinum".to_string()) {
             Some(m) => m,
             None => {
-                return Err("Can't find prosperity base cards".to_string());
+                return Err(DeckerError::Unsatisfiable(
+                    "Can't find prosperity base cards".to_string(),
+                ));
             }
         };
         let colony = match col.get_pile_for_card(&"Colony".to_string()) {
             Some(m) => m,
             None => {
-                return Err("Can't find prosperity base cards".to_string());
+                return Err(DeckerError::Unsatisfiable(
+                    "Can't find prosperity base cards".to_string(),
+                ));
             }
         };
         if !new_sel.contains(&platinum) {
             if !new_sel.add_pile(&platinum) {
-                return Err("Error adding Platinum".to_string());
+                return Err(DeckerError::Unsatisfiable(
+                    "Error adding Platinum".to_string(),
+                ));
             }
             new_sel.tag_pile(&platinum, &label.to_string());
         };
         if !new_sel.contains(&colony) {
             if !new_sel.add_pile(&colony) {
-                return Err("Error adding Colony".to_string());
+                return Err(DeckerError::Unsatisfiable(
+                    "Error adding Colony".to_string(),
+                ));
             }
             new_sel.tag_pile(&colony, &label.to_string());
         };
@@ -1,4 +1,3 @@
-use rand::Rng;
 use rand_core::{impls, Error, RngCore};
 
 // This was hidden as an implementation detail in c++
@@ -39,7 +38,85 @@ impl RngCore for BadRand {
     }
 
     fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
-        Ok(self.fill_bytes(dest))
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+// Default generator: xoshiro256** (Blackman and Vigna, 2018),
+// with the state filled from SplitMix64 as the authors recommend.
+// Both are a handful of shifts, xors and wrapping multiplies on u64
+// so they can be re-implemented bit-for-bit in other languages:
+//   https://prng.di.unimi.it/xoshiro256starstar.c
+//   https://prng.di.unimi.it/splitmix64.c
+// next_u32 uses the upper half of next_u64.
+struct Xoshiro {
+    s: [u64; 4],
+}
+
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+impl Xoshiro {
+    fn new(seed: u64) -> Self {
+        let mut sm = seed;
+        let s = [
+            splitmix64(&mut sm),
+            splitmix64(&mut sm),
+            splitmix64(&mut sm),
+            splitmix64(&mut sm),
+        ];
+        Xoshiro { s }
+    }
+}
+
+impl RngCore for Xoshiro {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let s = &mut self.s;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        result
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        impls::fill_bytes_via_next(self, dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+// Which generator a run used. Needs to be reported along with the
+// seed for a run to be reproducible.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RandKind {
+    Bad,
+    Xoshiro,
+}
+
+impl RandKind {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            RandKind::Bad => "badrand",
+            RandKind::Xoshiro => "xoshiro256**",
+        }
     }
 }
 
@@ -49,12 +126,18 @@ fn is_prime(num: u64) -> bool {
         return false;
     }
     let root = (num as f64).sqrt() as u64;
-    (2..=root).into_iter().find(|i| num % i == 0).is_none()
+    (2..=root)
+        .into_iter()
+        .find(|i| num.is_multiple_of(*i))
+        .is_none()
 }
 
-pub fn get_rand_stream(seed: u64, bound: u64, _use_bad_random: bool) -> impl Rng {
-    // eventually want to make this conditional on use_bad_random
-    BadRand::new(seed, bound)
+// bound is only used by the bad generator
+pub fn get_rand_stream(seed: u64, bound: u64, kind: RandKind) -> Box<dyn RngCore> {
+    match kind {
+        RandKind::Bad => Box::new(BadRand::new(seed, bound)),
+        RandKind::Xoshiro => Box::new(Xoshiro::new(seed)),
+    }
 }
 
 #[cfg(test)]
@@ -63,15 +146,33 @@ mod test_primes {
 
     #[test]
     fn test_first_numbers() {
-        assert!(is_prime(1) == false);
-        assert!(is_prime(2) == true);
-        assert!(is_prime(3) == true);
-        assert!(is_prime(4) == false);
+        assert!(!is_prime(1));
+        assert!(is_prime(2));
+        assert!(is_prime(3));
+        assert!(!is_prime(4));
     }
 
     #[test]
     fn test_larger_numbers() {
-        assert!(is_prime(997) == true);
-        assert!(is_prime(524) == false);
+        assert!(is_prime(997));
+        assert!(!is_prime(524));
+    }
+}
+
+#[cfg(test)]
+mod test_xoshiro {
+    use super::*;
+
+    // Values from the reference C code seeded the same way
+    #[test]
+    fn test_reference_values() {
+        let mut r = Xoshiro::new(0);
+        assert_eq!(r.next_u64(), 11091344671253066420);
+        assert_eq!(r.next_u64(), 13793997310169335082);
+        assert_eq!(r.next_u64(), 1900383378846508768);
+        let mut r = Xoshiro::new(54);
+        assert_eq!(r.next_u64(), 5431691590304511332);
+        assert_eq!(r.next_u64(), 2108556183456196834);
+        assert_eq!(r.next_u64(), 9902611950645328975);
     }
 }
@@ -3,14 +3,16 @@ use std::rc::Rc;
 
 use std::fs::File;
 use std::hash::{Hash, Hasher};
-use std::io::{BufRead, BufReader};
+use std::io::{BufReader, Read};
 use std::path::Path;
 
-use serde::Deserialize;
-
-use crate::costs::{decode_cost, Cost, CostTarget};
+use crate::costs::Cost;
+use crate::errors::{DeckerError, DeckerResult};
+use crate::interactions::Interaction;
 use crate::piles::{Pile, PilePtr};
-use crate::{bool_value, no_empty_split, string_split};
+
+use serde::de::{self, Deserializer, Unexpected};
+use serde::Deserialize;
 
 // What to do about vectors?
 #[derive(Debug)]
@@ -24,8 +26,7 @@ pub struct Card {
     cost: Cost,
     keywords: Vec<String>,
     kw_interactions: Vec<String>,
-    other_interactions: Vec<String>,
-    pub cost_targets: Vec<Box<dyn CostTarget>>,
+    other_interactions: Vec<Interaction>,
 }
 
 pub type CardPtr = Rc<Card>;
@@ -35,6 +36,7 @@ impl Card {
     // need to work out how many of these need to be copies and how many can be moved
     // Look at uses for Card constructor and work this out
     // A move constructor would have been fine for what I used it for
+    #[allow(clippy::too_many_arguments)]
     fn new(
         card_name: &str,
         card_pile: &str,
@@ -45,8 +47,7 @@ impl Card {
         c: &Cost,
         card_keywords: Vec<String>,
         interacts_keywords: Vec<String>,
-        interacts_other: Vec<String>,
-        targets: Vec<Box<dyn CostTarget>>,
+        interacts_other: Vec<Interaction>,
     ) -> Card {
         Card {
             name: String::from(card_name),
@@ -59,7 +60,6 @@ impl Card {
             keywords: card_keywords,
             kw_interactions: interacts_keywords,
             other_interactions: interacts_other,
-            cost_targets: targets,
         }
     }
     pub fn get_name(&self) -> &str {
@@ -89,7 +89,7 @@ impl Card {
     pub fn get_kw_interactions(&self) -> &[String] {
         &self.kw_interactions
     }
-    pub fn get_other_interactions(&self) -> &[String] {
+    pub fn get_other_interactions(&self) -> &[Interaction] {
         &self.other_interactions
     }
 }
@@ -111,89 +111,157 @@ impl Hash for Card {
 // typedef not declared like variables
 pub type CardSet = HashSet<CardPtr>;
 
-// #[derive(Debug, Deserialize)]
-// pub struct CSVCard {
-//     name: String,
-//     pile: String,
-//     card_group: String,
-//     supply: bool,
-//     kingdom: bool,
-//     types: Vec<String>,
-//     cost: Cost,
-//     keywords: Vec<String>,
-//     kw_interactions: Vec<String>,
-//     other_interactions: Vec<String>,
-//     cost_targets: Targets,
-// }
-
-fn make_card(fields: &Vec<String>) -> Option<Card> {
-    const NAMECOL: usize = 0;
-    const PILECOL: usize = 1;
-    const SETCOL: usize = 2;
-    const SUPPLYCOL: usize = 3;
-    const KINGDOMCOL: usize = 4;
-    const TYPECOL: usize = 5;
-    const COINCOST: usize = 6;
-    //     let SPENDPOW=7;
-    const DEBTCOST: usize = 8;
-    const POTIONCOST: usize = 9;
-    //     let POINTSCOL=10;
-    const KEYWORDSCOL: usize = 11;
-    const INTERACTKEY: usize = 12;
-    const INTERACTOTHER: usize = 13;
-    const END_COL: usize = INTERACTOTHER + 1;
-    if fields.len() < END_COL {
-        return None;
+// Header names for the columns we use. Other columns (eg comments)
+// are ignored and columns can be in any order.
+const NAME_COL: &str = "Card title";
+const OTHER_COL: &str = "other interacts";
+const YES_NO_COLS: [&str; 2] = ["in supply", "is kingdom"];
+const COST_COLS: [&str; 3] = ["cost", "potion cost", "debt"];
+
+fn bool_from_string<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match String::deserialize(deserializer)?.as_ref() {
+        "y" | "Y" => Ok(true),
+        "n" | "N" => Ok(false),
+        other => Err(de::Error::invalid_value(Unexpected::Str(other), &"y or n")),
     }
-    let coin_cost = fields[COINCOST].parse::<i8>().ok();
-    let potion_cost = fields[POTIONCOST].parse::<i8>().ok();
-    let debt_cost = fields[DEBTCOST].parse::<i8>().ok();
-    let c = Cost::new(coin_cost, potion_cost, debt_cost);
-
-    let in_supply = bool_value(&fields[SUPPLYCOL]);
-    let is_kingdom = bool_value(&fields[KINGDOMCOL]);
-
-    let types = no_empty_split(&fields[TYPECOL], ';');
-    let keywords = no_empty_split(&fields[KEYWORDSCOL], ';');
-    let interacts_kw = no_empty_split(&fields[INTERACTKEY], ';');
-    let interacts_other = no_empty_split(&fields[INTERACTOTHER], ';');
-    let mut targets: Vec<Box<dyn CostTarget>> = vec![];
-
-    // Recognise cost constraints and check
-    for s in &interacts_other {
-        if s.contains('(') && !s.ends_with(')') {
-            return None;
-        }
-        if s.starts_with("cost") {
-            match decode_cost(s) {
-                None => {
-                    return None;
-                }
-                Some(c) => {
-                    targets.push(c);
-                }
-            }
+}
+
+// Lists within a cell are ; separated (spaces around items are dropped)
+fn vec_from_string<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let raw: String = String::deserialize(deserializer)?;
+    if raw.is_empty() {
+        return Ok(Vec::new());
+    }
+    Ok(raw.split(';').map(|s| s.trim().to_string()).collect())
+}
+
+// Interactions can also be comma separated (in a quoted cell)
+// eg "group(Nocturne-States),card(Lucky Coin)"
+fn interactions_from_string<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let raw: String = String::deserialize(deserializer)?;
+    if raw.is_empty() {
+        return Ok(Vec::new());
+    }
+    Ok(raw
+        .split([';', ','])
+        .map(|s| s.trim().to_string())
+        .collect())
+}
+
+// An empty cell is no cost of that kind
+fn cost_from_string<'de, D>(deserializer: D) -> Result<Option<i8>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let raw: String = String::deserialize(deserializer)?;
+    if raw.is_empty() {
+        return Ok(None);
+    }
+    match raw.parse::<i8>() {
+        Ok(v) if v >= 0 => Ok(Some(v)),
+        _ => Err(de::Error::invalid_value(
+            Unexpected::Str(&raw),
+            &"a whole number or nothing",
+        )),
+    }
+}
+
+// One row of the card file as it comes out of the csv reader
+#[derive(Debug, Deserialize)]
+struct CardRow {
+    #[serde(rename = "Card title")]
+    name: String,
+    #[serde(rename = "Pile title")]
+    pile: String,
+    #[serde(rename = "Group")]
+    card_group: String,
+    #[serde(rename = "in supply", deserialize_with = "bool_from_string")]
+    supply: bool,
+    #[serde(rename = "is kingdom", deserialize_with = "bool_from_string")]
+    kingdom: bool,
+    #[serde(deserialize_with = "vec_from_string")]
+    types: Vec<String>,
+    #[serde(rename = "cost", deserialize_with = "cost_from_string")]
+    coin: Option<i8>,
+    #[serde(rename = "potion cost", deserialize_with = "cost_from_string")]
+    potion: Option<i8>,
+    #[serde(deserialize_with = "cost_from_string")]
+    debt: Option<i8>,
+    #[serde(deserialize_with = "vec_from_string")]
+    keywords: Vec<String>,
+    #[serde(
+        rename = "interacts with keywords",
+        deserialize_with = "vec_from_string"
+    )]
+    kw_interactions: Vec<String>,
+    #[serde(
+        rename = "other interacts",
+        deserialize_with = "interactions_from_string"
+    )]
+    other_interactions: Vec<String>,
+}
+
+// On failure, gives back a message about the other interacts column.
+// Interactions which aren't recognised at all are added to unknown.
+fn make_card(row: CardRow, unknown: &mut Vec<String>) -> Result<Card, String> {
+    let c = Cost::new(row.coin, row.potion, row.debt);
+    let mut interactions = vec![];
+    for s in &row.other_interactions {
+        let i = Interaction::parse(s)?;
+        if let Interaction::Unknown(u) = &i {
+            unknown.push(u.to_string());
         }
+        interactions.push(i);
     }
-    Some(Card::new(
-        &fields[NAMECOL],
-        &fields[PILECOL],
-        &fields[SETCOL],
-        in_supply,
-        is_kingdom,
-        types,
+    Ok(Card::new(
+        &row.name,
+        &row.pile,
+        &row.card_group,
+        row.supply,
+        row.kingdom,
+        row.types,
         &c,
-        keywords,
-        interacts_kw,
-        interacts_other,
-        targets,
+        row.keywords,
+        row.kw_interactions,
+        interactions,
     ))
 }
 
 pub fn load_cards(
     card_filename: &String,
     exclude_names: &mut HashMap<String, bool>,
-) -> Result<Vec<PilePtr>, String> {
+    warnings: &mut Vec<String>,
+) -> DeckerResult<Vec<PilePtr>> {
+    let ifs = match File::open(Path::new(card_filename)) {
+        Err(e) => {
+            return Err(DeckerError::FileIO {
+                filename: card_filename.to_string(),
+                reason: e.to_string(),
+            })
+        }
+        Ok(f) => f,
+    };
+    load_cards_from(BufReader::new(ifs), card_filename, exclude_names, warnings)
+}
+
+// Same as load_cards but for data which is already in memory
+// (or any other reader). source is used to label errors.
+// Problems which don't stop loading (eg unknown interactions) go in warnings.
+pub fn load_cards_from<R: Read>(
+    input: R,
+    source: &str,
+    exclude_names: &mut HashMap<String, bool>,
+    warnings: &mut Vec<String>,
+) -> DeckerResult<Vec<PilePtr>> {
     let mut card_piles: Vec<Pile> = vec![];
 
     // piles we need to remove before handing card_piles back
@@ -201,33 +269,115 @@ pub fn load_cards(
     // find things in card_piles using their name
     let mut p_map: BTreeMap<String, usize> = BTreeMap::new();
 
-    let ifs = match File::open(Path::new(card_filename)) {
-        Err(_) => return Err("Can't open file".to_string()),
-        Ok(f) => f,
+    let mut errors: Vec<DeckerError> = vec![];
+    let parse_error = |line: u64, column: Option<usize>, message: String| DeckerError::Parse {
+        source: source.to_string(),
+        line: line as usize,
+        column,
+        message,
     };
 
-    let input = BufReader::new(ifs);
-
-    let mut linecount: u16 = 1;
-    let mut error: String = "".to_string();
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(input);
+    let headers = match reader.headers() {
+        Ok(h) => h.clone(),
+        Err(e) => return Err(parse_error(1, None, e.to_string())),
+    };
+    let column_of = |name: &str| headers.iter().position(|h| h == name);
+    let (name_col, other_col) = match (column_of(NAME_COL), column_of(OTHER_COL)) {
+        (Some(n), Some(o)) => (n, o),
+        _ => {
+            return Err(parse_error(
+                1,
+                None,
+                format!(
+                    "header must name \"{}\" and \"{}\" columns",
+                    NAME_COL, OTHER_COL
+                ),
+            ))
+        }
+    };
 
-    for item in input.lines().skip(1) {
-        let line: String = match item {
-            Err(_) => continue,
-            Ok(l) => l,
+    for item in reader.records() {
+        let record = match item {
+            Err(e) => {
+                let line = e.position().map_or(0, |p| p.line());
+                errors.push(parse_error(line, None, e.to_string()));
+                continue;
+            }
+            Ok(r) => r,
         };
-        linecount += 1;
-        if line.starts_with(',') {
+        let line = record.position().map_or(0, |p| p.line());
+        let card_name = record.get(name_col).unwrap_or("");
+        // rows without a name are blank or separators
+        if card_name.is_empty() {
             continue;
         }
-        let comp = string_split(&line, ',');
-        let c = match make_card(&comp) {
-            None => {
-                error = format!("{}Error parsing card line {}\n", error, linecount);
+        let row: CardRow = match record.deserialize(Some(&headers)) {
+            Err(e) => {
+                let (column, message) = match e.kind() {
+                    csv::ErrorKind::Deserialize { err, .. } => {
+                        // errors raised by our own deserialize_with functions
+                        // don't know which field they came from
+                        let bad_cell = |names: &[&str], ok: &dyn Fn(&str) -> bool| {
+                            names.iter().find_map(|name| {
+                                let i = column_of(name)?;
+                                let v = record.get(i)?;
+                                (!ok(v)).then_some(i)
+                            })
+                        };
+                        let field = err
+                            .field()
+                            .map(|f| f as usize)
+                            .or_else(|| {
+                                bad_cell(&YES_NO_COLS, &|v| ["y", "Y", "n", "N"].contains(&v))
+                            })
+                            .or_else(|| {
+                                bad_cell(&COST_COLS, &|v| {
+                                    v.is_empty() || v.parse::<u8>().is_ok_and(|n| n < 128)
+                                })
+                            });
+                        match field {
+                            Some(f) => (
+                                Some(f + 1),
+                                format!(
+                                    "card {}, column \"{}\": {}",
+                                    card_name,
+                                    headers.get(f).unwrap_or(""),
+                                    err.kind()
+                                ),
+                            ),
+                            None => (None, format!("card {}: {}", card_name, err.kind())),
+                        }
+                    }
+                    _ => (None, format!("card {}: {}", card_name, e)),
+                };
+                errors.push(parse_error(line, column, message));
+                continue;
+            }
+            Ok(r) => r,
+        };
+        let mut unknown = vec![];
+        let c = match make_card(row, &mut unknown) {
+            Err(message) => {
+                errors.push(parse_error(
+                    line,
+                    Some(other_col + 1),
+                    format!("card {}, column \"{}\": {}", card_name, OTHER_COL, message),
+                ));
                 continue;
             }
-            Some(c) => c,
+            Ok(c) => c,
         };
+        for u in unknown {
+            warnings.push(format!(
+                "{}:{}:{}: card {}: unknown interaction \"{}\"",
+                source,
+                line,
+                other_col + 1,
+                card_name,
+                u
+            ));
+        }
 
         let pile_name = if c.get_pile_name().is_empty() {
             c.get_name()
@@ -269,18 +419,95 @@ pub fn load_cards(
             result_piles.push(PilePtr::new(p));
         }
     }
-    if error.is_empty()
+    if errors.is_empty()
     // only check for unknown card names if no error
     {
+        let mut unknown = BTreeSet::new();
         for (k, v) in exclude_names {
             if !*v {
-                error = format!("Unknown card {}", k);
-                break;
+                unknown.insert(k.to_string());
             }
         }
+        errors.extend(unknown.into_iter().map(DeckerError::UnknownCard));
+    }
+    match DeckerError::from_list(errors) {
+        Some(e) => Err(e),
+        None => Ok(result_piles),
+    }
+}
+
+#[cfg(test)]
+mod test_loader {
+    use super::*;
+
+    const HEADER: &str = "Card title,Pile title,Group,in supply,is kingdom,types,cost,spending power,debt,potion cost,Points,keywords,interacts with keywords,other interacts,comments\n";
+
+    fn load(rows: &str) -> DeckerResult<Vec<PilePtr>> {
+        let text = format!("{}{}", HEADER, rows);
+        load_cards_from(text.as_bytes(), "test", &mut HashMap::new(), &mut vec![])
+    }
+
+    #[test]
+    fn test_quoted_fields() {
+        let piles = load(
+            ",,,,,,,,,,,,,,\nFool,,Nocturne,y,y,Action;Fate,3,,,,,,,\"group(Nocturne-States),card(Lucky Coin)\",\"a, comment\"\n",
+        )
+        .unwrap();
+        assert_eq!(piles.len(), 1);
+        let card = &piles[0].get_cards()[0];
+        assert_eq!(
+            card.get_other_interactions(),
+            [
+                Interaction::Group("Nocturne-States".to_string()),
+                Interaction::Card("Lucky Coin".to_string())
+            ]
+        );
+        assert_eq!(card.get_types(), ["Action", "Fate"]);
+    }
+
+    #[test]
+    fn test_spaces() {
+        let mut warnings = vec![];
+        let text = format!(
+            "{}Teacher,,Adventures,y,y,Action;Reserve,6,,,,,, +card,card(A); cost<=4,\n",
+            HEADER
+        );
+        let piles =
+            load_cards_from(text.as_bytes(), "test", &mut HashMap::new(), &mut warnings).unwrap();
+        let card = &piles[0].get_cards()[0];
+        let inter = card.get_other_interactions();
+        assert_eq!(inter.len(), 2);
+        assert_eq!(inter[0], Interaction::Card("A".to_string()));
+        assert!(matches!(inter[1], Interaction::CostTarget(_)));
+        assert_eq!(card.get_kw_interactions(), ["+card"]);
+        assert!(warnings.is_empty());
     }
-    if !error.is_empty() {
-        return Err(error);
+
+    #[test]
+    fn test_row_errors() {
+        let res = load("Village,,Core,y,maybe,Action,3,,,,,+action,,,\n");
+        match res {
+            Err(DeckerError::Parse {
+                line,
+                column,
+                message,
+                ..
+            }) => {
+                assert_eq!(line, 2);
+                assert_eq!(column, Some(5));
+                assert!(message.contains("Village"));
+                assert!(message.contains("is kingdom"));
+            }
+            _ => panic!("expected parse error"),
+        }
+        match load("Village,,Core,y,y,Action,three,,,,,+action,,,\n") {
+            Err(DeckerError::Parse {
+                column, message, ..
+            }) => {
+                assert_eq!(column, Some(7));
+                assert!(message.contains("card Village, column \"cost\""));
+            }
+            _ => panic!("expected parse error"),
+        }
     }
-    Ok(result_piles)
 }
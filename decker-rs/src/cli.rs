@@ -0,0 +1,310 @@
+use clap::{Parser, Subcommand, ValueEnum};
+
+use decker::{ConfigBuilder, DeckerError, DeckerResult};
+
+#[derive(Parser)]
+pub struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Seed for random number generator (a random one is picked if not given).
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Use the old (poor quality) stepping random number generator instead of xoshiro256**
+    #[arg(long)]
+    badrand: bool,
+
+    /// Which boxes to include in the collection.
+    #[arg(long, value_delimiter = ',')]
+    boxes: Vec<String>,
+
+    /// Which groups to include in the collection.
+    #[arg(long, value_delimiter = ',')]
+    groups: Vec<String>,
+
+    /// Filename listing boxes and which groups they contain
+    #[arg(long)]
+    boxfile: Option<String>,
+
+    /// Filename listing all cards.
+    #[arg(long, global = true)]
+    cardfile: Option<String>,
+
+    /// Dump contents of collection and exit.
+    #[arg(long)]
+    list: bool,
+
+    /// How many landscape cards to include (does not include artefacts etc).
+    #[arg(long)]
+    landscape_count: Option<u8>,
+
+    /// Explain why cards were added.
+    #[arg(long)]
+    why: bool,
+
+    /// Do not validate collection.
+    #[arg(long)]
+    no_validate: bool,
+
+    /// Do not allow any of these cards.
+    #[arg(long, value_delimiter = ',')]
+    exclude: Vec<String>,
+
+    /// Leave out kingdom cards and landscapes with these types (eg Night).
+    #[arg(long, value_delimiter = ',')]
+    exclude_type: Vec<String>,
+
+    /// Leave out kingdom cards and landscapes with these keywords (eg curser).
+    #[arg(long, value_delimiter = ',')]
+    exclude_keyword: Vec<String>,
+
+    /// Leave out kingdom cards and landscapes from these groups or group prefixes (eg Nocturne).
+    #[arg(long, value_delimiter = ',')]
+    exclude_group: Vec<String>,
+
+    /// Leave out kingdom cards and landscapes with debt or potion costs.
+    #[arg(long, value_delimiter = ',')]
+    exclude_cost: Vec<String>,
+
+    /// This card must be in the selection.
+    #[arg(long, value_delimiter = ',')]
+    include: Vec<String>,
+
+    /// Rebuild the selection printed with this kingdom code instead of making a new one.
+    #[arg(long)]
+    load_code: Option<String>,
+
+    /// Check exactly these cards (card or pile names) and add only what they need.
+    #[arg(long, value_delimiter = ',')]
+    complete: Vec<String>,
+
+    /// File of cards for --complete (one per line or comma separated, # for comments).
+    #[arg(long)]
+    complete_file: Option<String>,
+
+    /// Reroll these cards out of the --load-code or --complete selection, keeping the rest.
+    #[arg(long, value_delimiter = ',')]
+    veto: Vec<String>,
+
+    /// How many selections to make.
+    #[arg(long, default_value_t = 1)]
+    count: usize,
+
+    /// No kingdom pile is used in more than one of the --count selections.
+    #[arg(long)]
+    disjoint: bool,
+
+    /// File of kingdoms already played, each selection made is added to it.
+    #[arg(long)]
+    history: Option<String>,
+
+    /// Leave out cards played in the last K games of --history.
+    #[arg(long, default_value_t = 0)]
+    history_exclude: usize,
+
+    /// Make cards played in the last K games of --history less likely (more so the more recent).
+    #[arg(long, default_value_t = 0)]
+    history_weight: usize,
+
+    /// Use --history without adding these selections to it.
+    #[arg(long)]
+    no_record: bool,
+
+    /// Make cards, piles, groups or group prefixes more or less likely, eg --weight=Seaside:2,Rats:0.1
+    #[arg(long, value_delimiter = ',')]
+    weight: Vec<String>,
+
+    /// File of settings, one "option = value" per line (eg weight = Seaside:2).
+    #[arg(long)]
+    config: Option<String>,
+
+    /// Show info about selected cards.
+    #[arg(long)]
+    info: bool,
+
+    /// How to print the selection.
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    /// Disable automatic adding reacts to attacks.
+    #[arg(long)]
+    no_attack_react: bool,
+
+    /// Disable automatic adding of trash cards if cards give curses.
+    #[arg(long)]
+    no_anti_cursor: bool,
+
+    /// Set the maximum number of times a cost can occur.
+    #[arg(long, default_value_t = 0)]
+    max_cost_repeat: u8,
+
+    /// eg --min-type=Treasure:5 means that the selection will can contain at least 5 treasures."));
+    #[arg(long, value_delimiter = ',')]
+    min_type: Vec<String>,
+
+    /// eg --max-type=Treasure:5 means that the selection will can contain at most 5 treasures."));
+    #[arg(long, value_delimiter = ',')]
+    max_type: Vec<String>,
+
+    /// eg --min-keyword=+buy:1,+action:2 means at least one card giving +buy and two giving +action.
+    #[arg(long, value_delimiter = ',')]
+    min_keyword: Vec<String>,
+
+    /// eg --max-keyword=curser:1 means at most one card which gives curses.
+    #[arg(long, value_delimiter = ',')]
+    max_keyword: Vec<String>,
+
+    /// Never put these two cards in the same selection, eg --forbid-together=Witch+Mountebank
+    #[arg(long, value_delimiter = ',')]
+    forbid_together: Vec<String>,
+
+    /// If one of these two cards is picked, add the other, eg --require-together=Rats+Chapel
+    #[arg(long, value_delimiter = ',')]
+    require_together: Vec<String>,
+
+    /// eg --min-cost=2..3:3,5..:2 means at least three kingdom cards costing $2-$3 and two costing $5 or more.
+    #[arg(long, value_delimiter = ',')]
+    min_cost: Vec<String>,
+
+    /// eg --max-cost=6..:2 means at most two kingdom cards costing $6 or more.
+    #[arg(long, value_delimiter = ',')]
+    max_cost: Vec<String>,
+
+    /// eg --constraint='count(type:Action & kw:+action & !type:Attack) >= 2' (can be repeated).
+    #[arg(long)]
+    constraint: Vec<String>,
+
+    /// eg --per-group=Seaside:5,Guilds:5 means exactly 5 kingdom cards from each of Seaside and Guilds.
+    #[arg(long, value_delimiter = ',')]
+    per_group: Vec<String>,
+
+    /// Pick an expansion (group prefix) at random first, then a card from it, so small expansions come up as often as big ones.
+    #[arg(long)]
+    balance_groups: bool,
+
+    /// Most prefixes (groups and related groups) which can be included. Eg: Cornucopia would also allow Cornucopia-prizes.
+    #[arg(long, default_value_t = 0)]
+    max_prefixes: u8,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Text,
+    Json,
+    // setup sheets
+    Markdown,
+    Html,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Check the card file for misspelled types, keywords and interactions.
+    Lint,
+}
+
+// Split "Type:Int" items for option
+fn type_counts(option: &str, items: &[String]) -> DeckerResult<Vec<(String, u8)>> {
+    let mut res = vec![];
+    for s in items {
+        match s.split_once(':').map(|(lhs, rhs)| (lhs, rhs.parse::<u8>())) {
+            Some((lhs, Ok(count))) if !lhs.is_empty() => res.push((lhs.to_string(), count)),
+            _ => {
+                return Err(DeckerError::Config(format!(
+                    "--{} expects name:count not {}.",
+                    option, s
+                )))
+            }
+        }
+    }
+    Ok(res)
+}
+
+impl Cli {
+    pub fn get_command(&self) -> Option<&Command> {
+        self.command.as_ref()
+    }
+
+    pub fn get_format(&self) -> Format {
+        self.format
+    }
+
+    pub fn get_card_file<'a>(&'a self, default: &'a str) -> &'a str {
+        self.cardfile.as_deref().unwrap_or(default)
+    }
+
+    pub fn to_builder(&self, card_file: &str) -> DeckerResult<ConfigBuilder> {
+        let mut b = ConfigBuilder::new()
+            .bad_rand(self.badrand)
+            .card_file(self.get_card_file(card_file))
+            .boxes(&self.boxes)
+            .groups(&self.groups)
+            .exclude(&self.exclude)
+            .exclude_types(&self.exclude_type)
+            .exclude_keywords(&self.exclude_keyword)
+            .exclude_groups(&self.exclude_group)
+            .exclude_costs(&self.exclude_cost)
+            .include(&self.include)
+            .complete(&self.complete)
+            .veto(&self.veto)
+            .count(self.count)
+            .disjoint(self.disjoint)
+            .history_exclude(self.history_exclude)
+            .history_weight(self.history_weight)
+            .record(!self.no_record)
+            .weights(&self.weight)
+            .forbid_together(&self.forbid_together)
+            .require_together(&self.require_together)
+            .min_costs(&self.min_cost)
+            .max_costs(&self.max_cost)
+            .why(self.why)
+            .more_info(self.info)
+            .validate(!self.no_validate)
+            .list_collection(self.list)
+            .disable_anti_cursors(self.no_anti_cursor)
+            .disable_attack_react(self.no_attack_react)
+            .max_cost_repeat(self.max_cost_repeat)
+            .balance_groups(self.balance_groups)
+            .max_prefixes(self.max_prefixes);
+        if let Some(seed) = self.seed {
+            b = b.seed(seed);
+        }
+        if let Some(fname) = &self.boxfile {
+            b = b.box_file(fname);
+        }
+        if let Some(code) = &self.load_code {
+            b = b.load_code(code);
+        }
+        if let Some(fname) = &self.complete_file {
+            b = b.complete_file(fname);
+        }
+        if let Some(fname) = &self.config {
+            b = b.config_file(fname);
+        }
+        if let Some(fname) = &self.history {
+            b = b.history_file(fname);
+        }
+        if let Some(count) = self.landscape_count {
+            b = b.landscape_count(count);
+        }
+        for (name, count) in type_counts("min-type", &self.min_type)? {
+            b = b.min_type(&name, count);
+        }
+        for (name, count) in type_counts("max-type", &self.max_type)? {
+            b = b.max_type(&name, count);
+        }
+        for (name, count) in type_counts("min-keyword", &self.min_keyword)? {
+            b = b.min_keyword(&name, count);
+        }
+        for (name, count) in type_counts("max-keyword", &self.max_keyword)? {
+            b = b.max_keyword(&name, count);
+        }
+        for text in &self.constraint {
+            b = b.constraint(text);
+        }
+        for (name, count) in type_counts("per-group", &self.per_group)? {
+            b = b.per_group(&name, count);
+        }
+        Ok(b)
+    }
+}
@@ -0,0 +1,284 @@
+use std::collections::BTreeSet;
+
+use crate::constraints::extra_tag;
+use crate::data_hash;
+use crate::errors::{DeckerError, DeckerResult};
+use crate::interactions::Interaction;
+use crate::piles::{PilePtr, PileSet};
+use crate::selections::SelectionPtr;
+
+// Kingdom codes are for pasting into chat instead of a list of names.
+// Layout (all base32 digits):
+//   version (1 digit)
+//   hash of the pile names in the card database (6 digits)
+//   flags (1 digit) bit 0 => Shelters were added, bit 1 => Colony and Platinum
+//   one fixed width number per kingdom pile, landscape or pile added for a
+//   cost target (those depend on search order): index*2 + extra
+//     where index is the position of the pile name in the sorted list of
+//     every pile in the database and extra is set for piles which filled
+//     an extra(...) slot (eg the bane)
+// Only the names feed the hash so fixing a typo in the keywords
+// doesn't break old codes but adding or removing a pile does.
+pub const CODE_VERSION: u32 = 1;
+
+const ALPHABET: &[u8; 32] = b"0123456789abcdefghjkmnpqrstvwxyz";
+const HASH_DIGITS: usize = 6;
+const SHELTERS_FLAG: u32 = 1;
+const PROSPERITY_FLAG: u32 = 2;
+
+pub(crate) const SHELTERS_NOTE: &str = "addedDarkAges-base";
+
+// What a code says, piles are (name, filled an extra slot).
+// None for the flags means choose as usual (eg for a list of card names).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KingdomCode {
+    pub piles: Vec<(String, bool)>,
+    pub shelters: Option<bool>,
+    pub prosperity: Option<bool>,
+}
+
+impl KingdomCode {
+    pub fn from_piles(piles: &PileSet) -> KingdomCode {
+        KingdomCode {
+            piles: piles
+                .iter()
+                .map(|p| (p.get_name().to_string(), false))
+                .collect(),
+            shelters: None,
+            prosperity: None,
+        }
+    }
+}
+
+// Maps between piles and their numbers for one card database
+#[derive(Debug, Clone)]
+pub struct CodeBook {
+    names: Vec<String>,
+    hash: u32,
+}
+
+fn digit_value(c: char) -> Option<u32> {
+    // be forgiving about case and the letters people mix up
+    let c = match c.to_ascii_lowercase() {
+        'o' => '0',
+        'i' | 'l' => '1',
+        c => c,
+    };
+    ALPHABET
+        .iter()
+        .position(|a| *a as char == c)
+        .map(|v| v as u32)
+}
+
+fn push_digits(out: &mut String, value: u32, width: usize) {
+    for i in (0..width).rev() {
+        out.push(ALPHABET[((value >> (5 * i)) & 31) as usize] as char);
+    }
+}
+
+fn read_digits(digits: &[u32]) -> u32 {
+    digits.iter().fold(0, |acc, d| (acc << 5) | d)
+}
+
+impl CodeBook {
+    pub fn new(piles: &[PilePtr]) -> CodeBook {
+        let names: BTreeSet<&str> = piles.iter().map(|p| p.get_name()).collect();
+        let names: Vec<String> = names.into_iter().map(|s| s.to_string()).collect();
+        let h = data_hash(names.join("\n").as_bytes());
+        CodeBook {
+            names,
+            hash: ((h ^ (h >> 32)) as u32) & ((1 << (5 * HASH_DIGITS)) - 1),
+        }
+    }
+
+    // digits needed for each pile
+    fn width(&self) -> usize {
+        let mut w = 1;
+        while (2 * self.names.len() as u64) >= (1 << (5 * w)) {
+            w += 1;
+        }
+        w
+    }
+
+    pub fn encode(&self, sel: &SelectionPtr) -> String {
+        let mut extras = BTreeSet::new();
+        for p in sel.get_piles() {
+            for it in p.get_other_interactions() {
+                if let Interaction::Extra { name, .. } = it {
+                    extras.insert(extra_tag(name));
+                }
+            }
+        }
+        let mut values = vec![];
+        for p in sel.get_piles() {
+            let tags = sel.get_tags(p);
+            let cost_target = tags.iter().any(|t| t.starts_with("<why?cost-target"));
+            if !(p.get_supply() && p.get_kingdom() || p.is_landscape() || cost_target) {
+                continue;
+            }
+            if let Ok(index) = self
+                .names
+                .binary_search_by(|n| n.as_str().cmp(p.get_name()))
+            {
+                let extra = tags.iter().any(|t| extras.contains(t));
+                values.push(2 * index as u32 + extra as u32);
+            }
+        }
+        values.sort();
+        let mut flags = 0;
+        if sel.get_notes().contains(SHELTERS_NOTE) {
+            flags |= SHELTERS_FLAG;
+        }
+        let has_card = |name: &str| sel.get_piles().iter().any(|p| p.get_name() == name);
+        if has_card("Colony") && has_card("Platinum") {
+            flags |= PROSPERITY_FLAG;
+        }
+        let mut res = String::new();
+        push_digits(&mut res, CODE_VERSION, 1);
+        push_digits(&mut res, self.hash, HASH_DIGITS);
+        push_digits(&mut res, flags, 1);
+        let width = self.width();
+        for v in values {
+            push_digits(&mut res, v, width);
+        }
+        res
+    }
+
+    pub fn decode(&self, code: &str) -> DeckerResult<KingdomCode> {
+        let bad = |s: String| Err(DeckerError::BadCode(s));
+        let mut digits = vec![];
+        for c in code.trim().chars() {
+            match digit_value(c) {
+                Some(d) => digits.push(d),
+                None => return bad(format!("'{}' can't appear in a kingdom code", c)),
+            }
+        }
+        if digits.is_empty() {
+            return bad("the code is empty".to_string());
+        }
+        if digits[0] != CODE_VERSION {
+            return bad(format!(
+                "{} is a version {} code, expected version {}",
+                code, digits[0], CODE_VERSION
+            ));
+        }
+        if digits.len() < 2 + HASH_DIGITS {
+            return bad(format!("{} is too short", code));
+        }
+        if read_digits(&digits[1..1 + HASH_DIGITS]) != self.hash {
+            return bad(format!(
+                "{} was made with a different card database (piles have been added or removed)",
+                code
+            ));
+        }
+        let width = self.width();
+        if !(digits.len() - 2 - HASH_DIGITS).is_multiple_of(width) {
+            return bad(format!("{} is the wrong length", code));
+        }
+        let flags = digits[1 + HASH_DIGITS];
+        let mut piles = vec![];
+        for chunk in digits[2 + HASH_DIGITS..].chunks(width) {
+            let v = read_digits(chunk) as usize;
+            match self.names.get(v / 2) {
+                Some(name) => piles.push((name.clone(), v % 2 == 1)),
+                None => return bad(format!("{} has a pile number out of range", code)),
+            }
+        }
+        Ok(KingdomCode {
+            piles,
+            shelters: Some(flags & SHELTERS_FLAG != 0),
+            prosperity: Some(flags & PROSPERITY_FLAG != 0),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test_codes {
+    use super::*;
+    use crate::collections::CardCollectionPtr;
+    use crate::piles::Pile;
+    use crate::ConfigBuilder;
+
+    fn book(names: &[&str]) -> CodeBook {
+        let piles: Vec<PilePtr> = names.iter().map(|n| PilePtr::new(Pile::new(n))).collect();
+        CodeBook::new(&piles)
+    }
+
+    #[test]
+    fn test_decode() {
+        let b = book(&["Village", "Smithy", "Lighthouse"]);
+        let mut code = String::new();
+        push_digits(&mut code, CODE_VERSION, 1);
+        push_digits(&mut code, b.hash, HASH_DIGITS);
+        push_digits(&mut code, SHELTERS_FLAG, 1);
+        push_digits(&mut code, 2 * 2 + 1, 1); // Village as an extra
+        push_digits(&mut code, 0, 1); // Lighthouse
+        assert_eq!(
+            b.decode(&code.to_uppercase()),
+            Ok(KingdomCode {
+                piles: vec![
+                    ("Village".to_string(), true),
+                    ("Lighthouse".to_string(), false)
+                ],
+                shelters: Some(true),
+                prosperity: Some(false),
+            })
+        );
+        // a different database
+        let other = book(&["Village", "Smithy", "Lighthouse", "Moat"]);
+        assert!(matches!(other.decode(&code), Err(DeckerError::BadCode(_))));
+        assert!(b.decode("2").is_err());
+        assert!(b.decode("1u!").is_err());
+    }
+
+    // visible tags (eg Bane) by pile name
+    fn piles_and_tags(sel: &SelectionPtr) -> Vec<(String, Vec<String>)> {
+        let mut res: Vec<(String, Vec<String>)> = sel
+            .get_piles()
+            .iter()
+            .map(|p| {
+                let tags = sel.get_tags(p).into_iter().filter(|t| !t.contains('<'));
+                (p.get_name().to_string(), tags.collect())
+            })
+            .collect();
+        res.sort();
+        res
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // 195 has Colony added for Prosperity and Platinum for a cost target
+        for seed in (1..=30).chain([195]) {
+            let mut conf = ConfigBuilder::new()
+                .card_file("../dat/cards.dat")
+                .seed(seed)
+                .build()
+                .unwrap();
+            let col = CardCollectionPtr::new_state(&conf.piles);
+            let (sel, _) = conf.make_selection(col).unwrap();
+            let code = conf.code_book.encode(&sel);
+
+            let mut conf = ConfigBuilder::new()
+                .card_file("../dat/cards.dat")
+                .seed(seed + 1000)
+                .load_code(&code)
+                .build()
+                .unwrap();
+            let col = CardCollectionPtr::new_state(&conf.piles);
+            let (loaded, _) = conf.make_selection(col).unwrap();
+            assert_eq!(
+                piles_and_tags(&sel),
+                piles_and_tags(&loaded),
+                "seed {}",
+                seed
+            );
+            // every pile still says why it is there
+            for p in loaded.get_piles() {
+                if p.get_kingdom() || p.is_landscape() {
+                    assert!(loaded.get_tags(p).iter().any(|t| t.starts_with("<why")));
+                }
+            }
+            assert_eq!(conf.code_book.encode(&loaded), code);
+        }
+    }
+}
@@ -1,13 +1,17 @@
 use std::borrow::Borrow;
 use std::cell::RefCell;
-use std::collections::{BTreeSet, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::rc::Rc;
 
 use rand::{Rng, RngCore};
 
 use crate::cards::{CardPtr, CardSet};
-use crate::constraints::{ConsResult, ConsResult::*, ConstraintPtr};
+use crate::codes::{KingdomCode, SHELTERS_NOTE};
+use crate::constraints::{extra_note, extra_tag, ConsResult, ConsResult::*, ConstraintPtr};
 use crate::costs::{Cost, CostSet, CostVotes};
+use crate::errors::{DeckerError, DeckerResult};
+use crate::group_name_prefix;
+use crate::interactions::Interaction;
 use crate::piles::{PilePtr, PileSet, SortablePile};
 use crate::properties::{PropLists, PropertyPtr};
 use crate::selections::{SelectionPtr, SelectionState};
@@ -49,23 +53,40 @@ pub struct CollectionState {
 }
 
 impl CollectionState {
-    pub(crate) fn validate_collection(&self) -> CollectionStatus {
+    pub fn validate_collection(&self) -> CollectionStatus {
+        // Problems which mean no selection can be made at all
+        let mut fatal: Vec<String> = vec![];
+        if !self.group_names.contains("base") {
+            fatal.push("Collection has no base cards.".to_string());
+        }
+        let kingdom_count = self
+            .piles
+            .iter()
+            .filter(|p| p.get_kingdom() && p.get_supply())
+            .count();
+        if kingdom_count < 10 {
+            fatal.push(format!(
+                "Collection has only {} kingdom piles (need at least 10).",
+                kingdom_count
+            ));
+        }
+        if !fatal.is_empty() {
+            return CollectionStatus::CollFatal(fatal);
+        }
         let mut warnings: Vec<String> = vec![];
         for p in &self.piles {
             for c in p.get_cards() {
                 for inter in c.get_other_interactions() {
-                    if inter.starts_with("card(") {
-                        let target = inter["card(".len()..inter.len() - 1].to_string();
-                        if !self.card_names.contains(&target) {
+                    if let Interaction::Card(target) = inter {
+                        if !self.card_names.contains(target) {
                             warnings.push(format!(
                                 "Card {} interacts with {} but it is missing.",
                                 c.get_name(),
                                 target
                             ));
                         }
-                    } else if inter.starts_with("group(") {
-                        let target = inter["group(".len()..inter.len() - 1].to_string();
-                        if !self.group_names.contains(&target) {
+                    } else if let Interaction::Group(target) = inter {
+                        if !self.group_names.contains(target) {
                             warnings.push(format!(
                                 "Card {} interacts with group {} but it is missing.",
                                 c.get_name(),
@@ -83,7 +104,81 @@ impl CollectionState {
         }
     }
 
-    pub(crate) fn shuffle(&mut self, r: &mut Box<dyn RngCore>) {
+    // Reorder the (already shuffled) piles so higher weights tend to come
+    // first. Weights are in thousandths and piles not mentioned get 1000.
+    // Sticks to integers so other implementations can give the same order.
+    pub fn apply_weights(&mut self, weights: &BTreeMap<String, u64>, r: &mut Box<dyn RngCore>) {
+        if weights.is_empty() {
+            return;
+        }
+        let mut rest: Vec<(PilePtr, u64)> = self
+            .piles
+            .drain(..)
+            .map(|p| {
+                let w = weights.get(p.get_name()).copied().unwrap_or(1000);
+                (p, w)
+            })
+            .collect();
+        let mut total: u64 = rest.iter().map(|(_, w)| w).sum();
+        while total > 0 {
+            let mut pick = r.gen::<u64>() % total;
+            let pos = rest
+                .iter()
+                .position(|(_, w)| {
+                    if pick < *w {
+                        return true;
+                    }
+                    pick -= w;
+                    false
+                })
+                .unwrap_or(0);
+            let (p, w) = rest.remove(pos);
+            total -= w;
+            self.piles.push(p);
+        }
+        // weight 0 goes last
+        self.piles.extend(rest.into_iter().map(|(p, _)| p));
+    }
+
+    // Expansion-first order for the kingdom piles: each place goes to a
+    // prefix picked with equal chance, which gives up its next pile (in the
+    // current order). Other piles stay where they are.
+    pub fn balance_prefixes(&mut self, r: &mut Box<dyn RngCore>) {
+        let mut queues = BTreeMap::<String, VecDeque<PilePtr>>::new();
+        let mut slots = vec![];
+        for (i, p) in self.piles.iter().enumerate() {
+            if p.get_kingdom() && p.get_supply() {
+                queues
+                    .entry(group_name_prefix(p.get_card_group()))
+                    .or_default()
+                    .push_back(p.clone());
+                slots.push(i);
+            }
+        }
+        for i in slots {
+            let pick = (r.gen::<u64>() % queues.len() as u64) as usize;
+            let prefix = match queues.keys().nth(pick) {
+                Some(k) => k.clone(),
+                None => break,
+            };
+            if let Some(q) = queues.get_mut(&prefix) {
+                if let Some(p) = q.pop_front() {
+                    self.piles[i] = p;
+                }
+                if q.is_empty() {
+                    queues.remove(&prefix);
+                }
+            }
+        }
+    }
+
+    // Take piles out of the search (after validation, so the support
+    // piles they leave behind don't matter)
+    pub fn retain_piles<F: Fn(&PilePtr) -> bool>(&mut self, keep: F) {
+        self.piles.retain(keep);
+    }
+
+    pub fn shuffle(&mut self, r: &mut Box<dyn RngCore>) {
         // go through the pile vector 3 times and swap items
         for _ in 0..3 {
             for j in (1..self.piles.len()).rev() {
@@ -102,17 +197,21 @@ pub struct CardColl {
 
 impl CardColl {
     // Wrapper for starting, building and finishing a selection
-    pub(crate) fn generate_selection(
+    pub fn generate_selection(
         &self,
         market_cap: u8,
         landscapes: u8,
         includes: &PileSet,
         cons: &Vec<ConstraintPtr>,
         rand: &mut Box<dyn RngCore>,
-    ) -> Result<SelectionPtr, String> {
+    ) -> DeckerResult<SelectionPtr> {
         let mut sel = match self.start_selection(market_cap, landscapes) {
             Some(s) => s,
-            None => return Err("".to_string()),
+            None => {
+                return Err(DeckerError::SearchExhausted(
+                    "Collection has no base or kingdom cards to start from".to_string(),
+                ))
+            }
         };
         for c in cons {
             sel.add_constraint(c.clone())
@@ -121,24 +220,129 @@ impl CardColl {
             sel.add_pile(p);
             sel.tag_pile(p, &"<why?--included>".to_string());
         }
-        let mut res = match self.build_selection(&SelectionPtr::from_state(sel)) {
-            Ok(s) => s,
-            Err(m) => return Err(m),
+        let mut res = self.build_selection(&SelectionPtr::from_state(sel))?;
+        let state = match Rc::get_mut(&mut res.state) {
+            Some(r) => r,
+            None => {
+                return Err(DeckerError::SearchExhausted(
+                    "Unexpected reference count".to_string(),
+                ))
+            }
         };
+        let shelters = self.roll_shelters(state, rand);
+        self.finish_selection(state, shelters);
+        Ok(res)
+    }
+
+    // Rebuild a selection from a kingdom code.
+    // The coded piles go in first and then the constraints are run
+    // to put back everything which was added automatically.
+    // If piles have been taken out of the code (see --veto) general
+    // piles fill the gaps.
+    pub fn load_selection(
+        &self,
+        code: &KingdomCode,
+        cons: &Vec<ConstraintPtr>,
+        rand: &mut Box<dyn RngCore>,
+    ) -> DeckerResult<SelectionPtr> {
+        let kingdom_count = code
+            .piles
+            .iter()
+            .filter(|(name, extra)| {
+                !extra
+                    && self
+                        .state
+                        .piles
+                        .iter()
+                        .any(|p| p.get_name() == name && p.get_supply() && p.get_kingdom())
+            })
+            .count();
+        let market_cap = u8::try_from(kingdom_count.max(10)).unwrap_or(u8::MAX);
+        let mut sel = match self.start_selection(market_cap, 0) {
+            Some(s) => s,
+            None => {
+                return Err(DeckerError::SearchExhausted(
+                    "Collection has no base or kingdom cards to start from".to_string(),
+                ))
+            }
+        };
+        for c in cons {
+            sel.add_constraint(c.clone())
+        }
+        let mut piles = vec![];
+        for (name, extra) in &code.piles {
+            match self.state.piles.iter().find(|p| p.get_name() == name) {
+                Some(p) => piles.push((p.clone(), *extra)),
+                None => {
+                    return Err(DeckerError::BadCode(format!(
+                        "{} is not in the collection (check --groups, --boxes and --exclude)",
+                        name
+                    )))
+                }
+            }
+        }
+        // extra slots are handed out in the order the piles needing them appear
+        let mut extra_names = vec![];
+        for (p, extra) in &piles {
+            if !extra {
+                for it in p.get_other_interactions() {
+                    if let Interaction::Extra { name, .. } = it {
+                        extra_names.push(name.clone());
+                    }
+                }
+            }
+        }
+        let mut extra_names = extra_names.into_iter();
+        for (p, extra) in &piles {
+            // if the pile which wanted it has been vetoed it's just a kingdom pile
+            let extra_name = match extra {
+                true => extra_names.next(),
+                false => None,
+            };
+            if let Some(name) = extra_name {
+                sel.increase_required_piles();
+                sel.add_pile(p);
+                sel.tag_pile(p, &extra_tag(&name));
+                sel.add_note(&extra_note(&name));
+            } else if !sel.add_pile(p) {
+                return Err(DeckerError::BadCode(format!(
+                    "no room to add {}",
+                    p.get_name()
+                )));
+            }
+            if p.get_kingdom() || p.is_landscape() {
+                sel.tag_pile(p, &"<why?locked>".to_string());
+            } else {
+                // only cost targets add other piles which go in the code
+                sel.tag_pile(p, &"<why?cost-target:locked>".to_string());
+            }
+        }
+        let mut res = self.build_selection(&SelectionPtr::from_state(sel))?;
         let state = match Rc::get_mut(&mut res.state) {
             Some(r) => r,
-            None => return Err("Unexpected reference count".to_string()),
+            None => {
+                return Err(DeckerError::SearchExhausted(
+                    "Unexpected reference count".to_string(),
+                ))
+            }
+        };
+        let shelters = match code.shelters {
+            // don't keep Shelters if all the DarkAges cards went
+            Some(b) => {
+                b && state
+                    .get_piles()
+                    .iter()
+                    .any(|p| p.get_kingdom() && p.get_card_group() == "DarkAges")
+            }
+            None => self.roll_shelters(state, rand),
         };
-        self.finish_selection(state, rand);
+        self.finish_selection(state, shelters);
         Ok(res)
     }
 
     fn start_selection(&self, market_cap: u8, landscapes: u8) -> Option<SelectionState> {
         let base = CardGroupProperty::make_ptr("base");
-        let begin = match self.get_iterators(&base) {
-            Some(v) => v,
-            None => return None,
-        };
+        let begin = self.get_iterators(&base)?;
         let begin_general = match self.get_iterators(&self.state.general_property) {
             Some(v) => v,
             None => {
@@ -175,12 +379,92 @@ impl CardColl {
         Some(new_sel)
     }
 
-    // Cleanup to check for extra elements like vp tokens which
-    // don't really need a constraint to catch
-    fn finish_selection(&self, sel: &mut SelectionState, rand: &mut Box<dyn RngCore>) {
-        // check to see if we need to add DarkAges-base cards
-        // rules say to do it based on randomness eg the last card added
-        // but we don't know what order things were drawn
+    // Run the constraints over a fixed kingdom without adding any general piles.
+    // Constraints which can't be met are dropped and handed back so the rest
+    // can still say what the kingdom needs.
+    pub fn complete_selection(
+        &self,
+        piles: &PileSet,
+        cons: &[ConstraintPtr],
+        rand: &mut Box<dyn RngCore>,
+    ) -> DeckerResult<(SelectionPtr, Vec<DeckerError>)> {
+        // no room for anything else in the kingdom
+        let market_cap = piles
+            .iter()
+            .filter(|p| p.get_supply() && p.get_kingdom())
+            .count();
+        let market_cap = match u8::try_from(market_cap) {
+            Ok(n) if n > 0 => n,
+            _ => {
+                return Err(DeckerError::Config(
+                    "Need between 1 and 255 kingdom cards to complete a kingdom".to_string(),
+                ))
+            }
+        };
+        let mut cons = cons.to_vec();
+        let mut problems = vec![];
+        loop {
+            let mut sel = match self.start_selection(market_cap, 0) {
+                Some(s) => s,
+                None => {
+                    return Err(DeckerError::SearchExhausted(
+                        "Collection has no base or kingdom cards to start from".to_string(),
+                    ))
+                }
+            };
+            for c in &cons {
+                sel.add_constraint(c.clone())
+            }
+            for p in piles {
+                sel.add_pile(p);
+                sel.tag_pile(p, &"<why?--complete>".to_string());
+            }
+            let start = SelectionPtr::from_state(sel);
+            match self.build_selection(&start) {
+                Ok(mut res) => {
+                    // res may be sharing start's state
+                    drop(start);
+                    let state = match Rc::get_mut(&mut res.state) {
+                        Some(r) => r,
+                        None => {
+                            return Err(DeckerError::SearchExhausted(
+                                "Unexpected reference count".to_string(),
+                            ))
+                        }
+                    };
+                    let shelters = self.roll_shelters(state, rand);
+                    self.finish_selection(state, shelters);
+                    return Ok((res, problems));
+                }
+                Err(e) => {
+                    // either it's broken outright or the first constraint
+                    // needing action couldn't find anything it was allowed to add
+                    let pos = match &e {
+                        DeckerError::Unsatisfiable(label) => {
+                            cons.iter().position(|c| c.get_label() == label)
+                        }
+                        _ => None,
+                    }
+                    .or_else(|| {
+                        cons.iter()
+                            .position(|c| c.get_status(&start) == ConsActionReq)
+                    });
+                    match pos {
+                        Some(i) => {
+                            let c = cons.remove(i);
+                            problems.push(DeckerError::Unsatisfiable(c.get_label().to_string()));
+                        }
+                        None => return Err(e),
+                    }
+                }
+            }
+        }
+    }
+
+    // check to see if we need to add DarkAges-base cards
+    // rules say to do it based on randomness eg the last card added
+    // but we don't know what order things were drawn
+    fn roll_shelters(&self, sel: &SelectionState, rand: &mut Box<dyn RngCore>) -> bool {
         // do we have any DarkAges cards
         let mut da_count = 0;
         let mut ks_count = 0; // count of kingdom and supply
@@ -196,20 +480,27 @@ impl CardColl {
             // if the random is less than the number of number of
             // DarkAges cards, add the DarkAges base cards to replace Estate
             let r = rand.gen::<u64>() % ks_count;
-            if r < da_count {
-                // need to add all piles from that group
-                let ps = CardGroupProperty::make_ptr("DarkAges-base");
-
-                // If we can't add this for some reason do nothing
-                if let Some(begin) = sel.get_collection().get_iterators(&ps) {
-                    for p in begin {
-                        if sel.add_pile(&p) {
-                            sel.tag_pile(&p, &"<why?had enough DarkAges cards>".to_string());
-                            sel.tag_pile(&p, &"Replaces Estate in starting deck".to_string());
-                        }
+            return r < da_count;
+        }
+        false
+    }
+
+    // Cleanup to check for extra elements like vp tokens which
+    // don't really need a constraint to catch
+    fn finish_selection(&self, sel: &mut SelectionState, shelters: bool) {
+        if shelters {
+            // need to add all piles from that group
+            let ps = CardGroupProperty::make_ptr("DarkAges-base");
+
+            // If we can't add this for some reason do nothing
+            if let Some(begin) = sel.get_collection().get_iterators(&ps) {
+                for p in begin {
+                    if sel.add_pile(&p) {
+                        sel.tag_pile(&p, &"<why?had enough DarkAges cards>".to_string());
+                        sel.tag_pile(&p, &"Replaces Estate in starting deck".to_string());
                     }
-                    sel.add_note(&"addedDarkAges-base".to_string());
                 }
+                sel.add_note(&SHELTERS_NOTE.to_string());
             }
         }
         for p in sel.get_piles() {
@@ -261,7 +552,7 @@ impl CardColl {
         }
     }
 
-    pub(crate) fn new_state(piles: &PileSet) -> CollectionState {
+    pub fn new_state(piles: &PileSet) -> CollectionState {
         let mut temp_vector = vec![];
         for v in piles {
             temp_vector.push(SortablePile { p: v.clone() });
@@ -299,11 +590,11 @@ impl CardColl {
         }
     }
 
-    pub(crate) fn from_state(c: CollectionState) -> CardColl {
+    pub fn from_state(c: CollectionState) -> CardColl {
         CardColl { state: Rc::new(c) }
     }
 
-    pub(crate) fn get_iterators(&self, p: &PropertyPtr) -> Option<CollectionIterator> {
+    pub fn get_iterators(&self, p: &PropertyPtr) -> Option<CollectionIterator> {
         let mut list_contents = self.state.lists.borrow_mut();
         // do we have this one?
         match list_contents.map.get(p) {
@@ -339,7 +630,7 @@ impl CardColl {
         }
     }
 
-    pub(crate) fn get_piles(&self) -> &Vec<PilePtr> {
+    pub fn get_piles(&self) -> &Vec<PilePtr> {
         &self.state.piles
     }
 
@@ -361,18 +652,17 @@ impl CardColl {
     // 3. Do any constraints have suggested actions?
     // 4. Anything to add based on cost targets?
     // 5. Try adding general cards (list of all available cards).
-    pub(crate) fn build_selection(&self, start: &SelectionPtr) -> Result<SelectionPtr, String> {
+    pub fn build_selection(&self, start: &SelectionPtr) -> DeckerResult<SelectionPtr> {
         let temp: &RefCell<Vec<ConstraintPtr>> = start.state.constraints.borrow();
         let constraints = temp.borrow();
         //let constraints:&mut Vec<ConstraintPtr> = start.state.constraints.borrow_mut();
         let size = constraints.len();
-        let mut status: Vec<ConsResult> = vec![];
-        status.reserve(size);
+        let mut status: Vec<ConsResult> = Vec::with_capacity(size);
         // see if we are breaking any constraints
         for c in &*constraints {
             let stat = c.get_status(start);
             if stat == ConsFail {
-                return Err("Constraint Fail".to_string());
+                return Err(DeckerError::Unsatisfiable(c.get_label().to_string()));
             }
             status.push(stat)
         }
@@ -434,7 +724,9 @@ impl CardColl {
                         if need_target_action
                         // need to check if we _needed_ it
                         {
-                            return Err("Needed target action".to_string());
+                            return Err(DeckerError::SearchExhausted(
+                                "No piles match the costs wanted by cost targets".to_string(),
+                            ));
                         }
                     }
                     Some(begin) => {
@@ -456,7 +748,10 @@ impl CardColl {
                                     start.set_need_to_check(false, &"".to_string()); // *start* is not a mistake
                                     break; // need to prevent _current_ selection from seeking more, not just the recursive one
                                 } else {
-                                    return Err("".to_string());
+                                    return Err(DeckerError::SearchExhausted(format!(
+                                        "No room to add {} for cost targets",
+                                        next.get_name()
+                                    )));
                                 }
                             }
                             // need to work out how to give more useful feedback
@@ -486,17 +781,22 @@ impl CardColl {
             }
             let mut new_sel = start.duplicate_state();
             if !new_sel.add_pile(&gen) {
-                return Err("".to_string());
+                return Err(DeckerError::SearchExhausted(format!(
+                    "No room to add {}",
+                    gen.get_name()
+                )));
             }
             new_sel.tag_pile(&gen, &"<why?general>".to_string());
             if let Ok(v) = self.build_selection(&SelectionPtr::from_state(new_sel)) {
                 return Ok(v);
             }
         }
-        Err("".to_string())
+        Err(DeckerError::SearchExhausted(
+            "Ran out of general piles to try".to_string(),
+        ))
     }
 
-    pub(crate) fn get_pile_for_card(&self, s: &String) -> Option<PilePtr> {
+    pub fn get_pile_for_card(&self, s: &String) -> Option<PilePtr> {
         for c in &self.state.cards {
             if c.get_name() == s {
                 let pn = if c.get_pile_name().is_empty() {
@@ -518,7 +818,182 @@ impl CardColl {
 pub enum CollectionStatus {
     CollOK,
     CollWarning(Vec<String>),
-    _CollFatal(Vec<String>),
+    CollFatal(Vec<String>),
 }
 
 pub type CardCollectionPtr = CardColl;
+
+#[cfg(test)]
+mod test_collections {
+    use super::*;
+    use crate::ConfigBuilder;
+
+    const CARDS: &str = "\
+Card title,Pile title,Group,in supply,is kingdom,types,cost,spending power,debt,potion cost,Points,keywords,interacts with keywords,other interacts,comments
+Copper,,base,y,n,Treasure,0,1,,,,,,,
+Village,,Core,y,y,Action,3,,,,,+action,,,
+Smithy,,Core,y,y,Action,4,,,,,+card,,,
+Moat,,Core-extra,y,y,Action;Reaction,2,,,,,+card,,,
+Lighthouse,,Seaside,y,y,Action;Duration,2,,,,,+action,,,
+";
+
+    // hands out the given numbers in turn, so the orders below don't
+    // depend on any real generator
+    struct Scripted {
+        values: Vec<u64>,
+        next: usize,
+    }
+
+    impl RngCore for Scripted {
+        fn next_u32(&mut self) -> u32 {
+            self.next_u64() as u32
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            let v = self.values[self.next % self.values.len()];
+            self.next += 1;
+            v
+        }
+
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            rand_core::impls::fill_bytes_via_next(self, dest)
+        }
+
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+            self.fill_bytes(dest);
+            Ok(())
+        }
+    }
+
+    fn scripted(values: &[u64]) -> Box<dyn RngCore> {
+        Box::new(Scripted {
+            values: values.to_vec(),
+            next: 0,
+        })
+    }
+
+    // the CARDS piles in the given order
+    fn state(names: &[&str]) -> CollectionState {
+        let conf = ConfigBuilder::new().card_data(CARDS).build().unwrap();
+        let mut col = CardCollectionPtr::new_state(&conf.piles);
+        col.piles = names
+            .iter()
+            .map(|n| {
+                conf.piles
+                    .iter()
+                    .find(|p| p.get_name() == *n)
+                    .unwrap()
+                    .clone()
+            })
+            .collect();
+        col
+    }
+
+    fn order(col: &CollectionState) -> Vec<&str> {
+        col.piles.iter().map(|p| p.get_name()).collect()
+    }
+
+    #[test]
+    fn test_weights() {
+        let names = ["Copper", "Village", "Smithy", "Lighthouse"];
+        let weights = |items: &[(&str, u64)]| {
+            BTreeMap::from_iter(items.iter().map(|(n, w)| (n.to_string(), *w)))
+        };
+        // no weights leaves the order alone
+        let mut col = state(&names);
+        col.apply_weights(&BTreeMap::new(), &mut scripted(&[2500]));
+        assert_eq!(order(&col), names);
+        // 0 always picks the first pile left so only weight 0 moves (to the end)
+        let mut col = state(&names);
+        col.apply_weights(&weights(&[("Village", 0)]), &mut scripted(&[0]));
+        assert_eq!(
+            order(&col),
+            vec!["Copper", "Smithy", "Lighthouse", "Village"]
+        );
+        // Smithy covers 2000..5000 of the 6000 so 2500 picks it first,
+        // then 2500 of 3000 lands on Lighthouse
+        let mut col = state(&names);
+        col.apply_weights(&weights(&[("Smithy", 3000)]), &mut scripted(&[2500]));
+        assert_eq!(
+            order(&col),
+            vec!["Smithy", "Lighthouse", "Copper", "Village"]
+        );
+        // with a weight of 1 the same number passes Smithy by
+        let mut col = state(&names);
+        col.apply_weights(&weights(&[("Smithy", 1)]), &mut scripted(&[2500]));
+        assert_eq!(
+            order(&col),
+            vec!["Lighthouse", "Copper", "Village", "Smithy"]
+        );
+        // played last game so its weight drops
+        let fname = std::env::temp_dir().join("decker_test_weights_history");
+        std::fs::write(&fname, "100\tSmithy\n").unwrap();
+        let conf = ConfigBuilder::new()
+            .card_data(CARDS)
+            .weights(&["Smithy:3"])
+            .history_file(fname.to_str().unwrap())
+            .history_weight(5)
+            .build()
+            .unwrap();
+        assert!(
+            (1..3000).contains(&conf.weights["Smithy"]),
+            "{:?}",
+            conf.weights
+        );
+        assert_eq!(conf.weights["Village"], 1000);
+    }
+
+    #[test]
+    fn test_complete() {
+        let mut conf = ConfigBuilder::new()
+            .card_file("../dat/cards.dat")
+            .seed(1)
+            .complete(&["Village", "Smithy"])
+            .max_type("Action", 1)
+            .build()
+            .unwrap();
+        let col = CardCollectionPtr::from_state(CardCollectionPtr::new_state(&conf.piles));
+        let cons = conf.build_constraints(&col).unwrap();
+        let piles = conf.complete.clone().unwrap();
+        let (sel, problems) = col
+            .complete_selection(&piles, &cons, &mut conf.rand)
+            .unwrap();
+        // no kingdom piles get added to fix it
+        let names: Vec<&str> = sel
+            .get_piles()
+            .iter()
+            .filter(|p| p.get_kingdom() && p.get_supply())
+            .map(|p| p.get_name())
+            .collect();
+        assert_eq!(names, vec!["Smithy", "Village"]);
+        assert_eq!(
+            problems,
+            vec![DeckerError::Unsatisfiable("At most 1 Actions".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_balance_prefixes() {
+        // Moat is Core-extra so Core has three piles to Seaside's one
+        let names = ["Village", "Copper", "Smithy", "Moat", "Lighthouse"];
+        // the prefixes are picked in name order, Core is 0 and Seaside 1
+        let mut col = state(&names);
+        col.balance_prefixes(&mut scripted(&[0]));
+        assert_eq!(order(&col), names);
+        // Copper isn't a kingdom pile so it stays put
+        let mut col = state(&names);
+        col.balance_prefixes(&mut scripted(&[1]));
+        assert_eq!(
+            order(&col),
+            vec!["Lighthouse", "Copper", "Village", "Smithy", "Moat"]
+        );
+        // a prefix keeps its own order, and once it runs out the others
+        // take the rest
+        let mut col = state(&names);
+        col.balance_prefixes(&mut scripted(&[0, 1]));
+        assert_eq!(
+            order(&col),
+            vec!["Village", "Copper", "Lighthouse", "Smithy", "Moat"]
+        );
+    }
+}
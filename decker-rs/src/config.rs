@@ -1,46 +1,97 @@
+use rand::rngs::OsRng;
 use rand::{Rng, RngCore};
-use std::collections::{BTreeSet, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
 use crate::actions::{
-    AddGroup, AddMissingDependency, AddMissingDependencyGroup, AddProsperity, FindPile,
+    AddGroup, AddMissingDependency, AddMissingDependencyGroup, AddMissingTypeDependency,
+    AddPartner, AddProsperity, FindPile,
 };
-use crate::bad_rand::get_rand_stream;
-use crate::cards::load_cards;
-use crate::collections::CardCollectionPtr;
+use crate::bad_rand::{get_rand_stream, RandKind};
+use crate::cards::load_cards_from;
+use crate::codes::{CodeBook, KingdomCode};
+use crate::collections::{CardCollectionPtr, CollectionState};
 use crate::constraints::{
-    attack_react_constraint, bane_constraint, curser_constraint, prosp_constraint, Constraint,
-    ConstraintPtr,
+    attack_react_constraint, curser_constraint, extra_pile_constraints, prosp_constraint,
+    Constraint, ConstraintPtr,
 };
-use crate::piles::PileSet;
+use crate::costs::CostBand;
+use crate::errors::{DeckerError, DeckerResult};
+use crate::expressions::{parse_constraint, ConstraintExpr};
+use crate::history::{read_history, recent_piles};
+use crate::interactions::Interaction;
+use crate::piles::{PilePtr, PileSet};
 use crate::properties::prelude::*;
+use crate::properties::PropertyPtr;
+use crate::selections::SelectionPtr;
 
-use crate::{group_name_prefix, read_boxes, Cli, MANY};
+use crate::{data_hash, group_name_prefix, parse_boxes, MANY};
 
 pub struct Config {
-    pub(crate) rand: Box<dyn RngCore>,
-    pub(crate) why: bool,
-    pub(crate) more_info: bool,
-    pub(crate) optional_extras: u8,
-    pub(crate) validate: bool,
-    pub(crate) list_collection: bool,
-    pub(crate) disable_anti_cursors: bool,
-    pub(crate) disable_attack_react: bool,
-    pub(crate) max_cost_repeat: u8,
-    pub(crate) min_types: HashMap<String, u8>,
-    pub(crate) max_types: HashMap<String, u8>,
-    pub(crate) piles: PileSet,
-    pub(crate) includes: PileSet,
+    pub rand: Box<dyn RngCore>,
+    pub rand_kind: RandKind,
+    pub seed: u64,
+    // canonical command line options for this run (see get_options_string)
+    pub options: String,
+    // "Options: ..." line which identifies how to reproduce this run
+    pub header: String,
+    pub why: bool,
+    pub more_info: bool,
+    pub optional_extras: u8,
+    pub validate: bool,
+    pub list_collection: bool,
+    pub disable_anti_cursors: bool,
+    pub disable_attack_react: bool,
+    pub max_cost_repeat: u8,
+    pub min_types: HashMap<String, u8>,
+    pub max_types: HashMap<String, u8>,
+    pub min_keywords: HashMap<String, u8>,
+    pub max_keywords: HashMap<String, u8>,
+    // kingdom piles in a band of costs
+    pub min_costs: Vec<(CostBand, u8)>,
+    pub max_costs: Vec<(CostBand, u8)>,
+    // pile names which can't both be in a selection
+    pub forbidden_pairs: Vec<(String, String)>,
+    // pile names which have to be in a selection together
+    pub required_pairs: Vec<(String, String)>,
+    // --constraint expressions, in the order given
+    pub expressions: Vec<ConstraintExpr>,
+    // exact number of kingdom piles from each group prefix
+    pub per_group: HashMap<String, u8>,
+    // pick an expansion first, then a pile from it
+    pub balance_groups: bool,
+    pub piles: PileSet,
+    pub includes: PileSet,
+    // numbers every pile in the card database (not just the collection)
+    pub code_book: CodeBook,
+    // rebuild this selection instead of generating one
+    pub load_code: Option<KingdomCode>,
+    // check and finish exactly this kingdom instead of generating one
+    pub complete: Option<PileSet>,
+    // how many selections to make and whether they can share kingdom piles
+    pub count: usize,
+    pub disjoint: bool,
+    // pile name => weight in thousandths for ordering the shuffled piles
+    // (see CollectionState::apply_weights), empty for a plain shuffle
+    pub weights: BTreeMap<String, u64>,
+    // where to record the selections made (see history.rs)
+    pub history_file: Option<String>,
+    // how many piles each --exclude-type etc filter took out
+    pub filter_counts: Vec<(String, usize)>,
+    // problems found loading the data which didn't stop us
+    pub warnings: Vec<String>,
 }
 
 impl Config {
     pub fn build_constraints(
         &mut self,
         col: &CardCollectionPtr,
-    ) -> Result<Vec<ConstraintPtr>, String> {
+    ) -> DeckerResult<Vec<ConstraintPtr>> {
         let fail_prop = FailProperty::make_ptr();
         let mut cons: Vec<ConstraintPtr> = vec![];
-        cons.push(bane_constraint(col));
-        cons.push(prosp_constraint(col));
+        cons.extend(extra_pile_constraints(col));
+        if self.load_code.as_ref().and_then(|c| c.prosperity).is_none() {
+            cons.push(prosp_constraint(col));
+        }
 
         // let's abuse the Constraint machinery
         // The missing poition check will be zero both when there are no Potion costs
@@ -62,11 +113,15 @@ impl Config {
         );
         cons.push(c);
 
+        // a kingdom code already says whether Colony and Platinum are in
+        // (1 => any Prosperity card, 0 => never)
+        let threshold = match self.load_code.as_ref().and_then(|c| c.prosperity) {
+            Some(b) => b as u8,
+            None => (self.rand.gen::<u64>() % 10).try_into().unwrap(),
+        };
         let c = Constraint::make_ptr_full(
             "AddProsperityCards".to_string(),
-            Some(NeedProsperity::make_ptr(
-                (self.rand.gen::<u64>() % 10).try_into().unwrap(),
-            )),
+            Some(NeedProsperity::make_ptr(threshold)),
             &fail_prop,
             Some(AddProsperity::make_ptr(col)),
             1,
@@ -100,6 +155,18 @@ impl Config {
         );
         cons.push(c);
 
+        let c = Constraint::make_ptr_full(
+            "AddInteractingType".to_string(),
+            Some(MissingInteractingTypeProperty::make_ptr()),
+            &fail_prop,
+            Some(AddMissingTypeDependency::make_ptr(col)),
+            1,
+            MANY,
+            MANY,
+            MANY,
+        );
+        cons.push(c);
+
         let c = Constraint::make_ptr_full(
             "AddHexForDoom".to_string(),
             Some(MissingGroupForKeywordProperty::make_ptr(
@@ -151,7 +218,7 @@ impl Config {
             cons.push(c);
         }
 
-        for (k, v) in &self.min_types {
+        for (k, v) in BTreeMap::<&String, &u8>::from_iter(&self.min_types) {
             // Note there are two TypeProperties in use here
             // When getting iterators for possible piles to add we only want to
             // consider piles which are in the supply and kingdom cards
@@ -165,7 +232,10 @@ impl Config {
             let t_begin = match col.get_iterators(&searcher) {
                 Some(v) => v,
                 None => {
-                    return Err(format!("No matches found for type {}", type_name));
+                    return Err(DeckerError::Unsatisfiable(format!(
+                        "No matches found for type {}",
+                        type_name
+                    )));
                 }
             };
             // we count all treasures, but we don't want non-kingdom cards to be selected to satisfy a constraint
@@ -179,7 +249,7 @@ impl Config {
             );
             cons.push(c);
         }
-        for (k, v) in &self.max_types {
+        for (k, v) in BTreeMap::<&String, &u8>::from_iter(&self.max_types) {
             let type_name = k;
             let type_count = v;
 
@@ -197,6 +267,144 @@ impl Config {
                 cons.push(c);
             }
         }
+        // Same split as for types: search kingdom and supply piles but
+        // count everything in the selection
+        for (keyword, count) in BTreeMap::<&String, &u8>::from_iter(&self.min_keywords) {
+            let searcher = KeywordProperty::make_ptr(keyword, true);
+            let begin = match col.get_iterators(&searcher) {
+                Some(v) => v,
+                None => {
+                    return Err(DeckerError::Unsatisfiable(format!(
+                        "No matches found for keyword {}",
+                        keyword
+                    )));
+                }
+            };
+            let c = Constraint::make_ptr(
+                format!("At least {} with keyword {}", count, keyword),
+                &KeywordProperty::make_ptr(keyword, false),
+                Some(FindPile::make_ptr(col, &begin)),
+                (*count).into(),
+                MANY,
+            );
+            cons.push(c);
+        }
+        for (keyword, count) in BTreeMap::<&String, &u8>::from_iter(&self.max_keywords) {
+            let prop = KeywordProperty::make_ptr(keyword, false);
+            if col.get_iterators(&prop).is_some() {
+                let c = Constraint::make_ptr(
+                    format!("At most {} with keyword {}", count, keyword),
+                    &prop,
+                    None,
+                    0,
+                    (*count).into(),
+                );
+                cons.push(c);
+            }
+        }
+        for (a, b) in &self.forbidden_pairs {
+            cons.push(Constraint::make_ptr(
+                format!("Not {} with {}", a, b),
+                &PairProperty::make_ptr(a, b),
+                None,
+                0,
+                0,
+            ));
+        }
+        // same trick as AddPotion: the precondition is only met with half
+        // a pair and the failing main property makes the action run
+        for (a, b) in &self.required_pairs {
+            cons.push(Constraint::make_ptr_full(
+                format!("{} with {}", a, b),
+                Some(HalfPairProperty::make_ptr(a, b)),
+                &fail_prop,
+                Some(AddPartner::make_ptr(col, a, b)),
+                1,
+                MANY,
+                MANY,
+                MANY,
+            ));
+        }
+        // cost bands only look at kingdom piles (Estate and Silver would
+        // make $2-$3 trivial)
+        for (band, count) in &self.min_costs {
+            let prop = AllProperty::make_ptr(&[
+                CostProperty::make_ptr_set(band.costs_from(col.get_piles()), true),
+                KingdomAndSupplyProperty::make_ptr(),
+            ]);
+            let begin = match col.get_iterators(&prop) {
+                Some(v) => v,
+                None => {
+                    return Err(DeckerError::Unsatisfiable(format!(
+                        "No kingdom piles cost {}",
+                        band.label()
+                    )));
+                }
+            };
+            cons.push(Constraint::make_ptr(
+                format!("At least {} costing {}", count, band.label()),
+                &prop,
+                Some(FindPile::make_ptr(col, &begin)),
+                (*count).into(),
+                MANY,
+            ));
+        }
+        for (band, count) in &self.max_costs {
+            let prop = AllProperty::make_ptr(&[
+                CostProperty::make_ptr_set(band.costs_from(col.get_piles()), true),
+                KingdomAndSupplyProperty::make_ptr(),
+            ]);
+            cons.push(Constraint::make_ptr(
+                format!("At most {} costing {}", count, band.label()),
+                &prop,
+                None,
+                0,
+                (*count).into(),
+            ));
+        }
+        for e in &self.expressions {
+            // only kingdom piles are added to reach the minimum
+            let find = if e.min > 0 {
+                let searcher = AllProperty::make_ptr(&[
+                    e.property.clone(),
+                    KingdomAndSupplyProperty::make_ptr(),
+                ]);
+                match col.get_iterators(&searcher) {
+                    Some(begin) => Some(FindPile::make_ptr(col, &begin)),
+                    None => {
+                        return Err(DeckerError::Unsatisfiable(format!(
+                            "No kingdom piles match {}",
+                            e.text
+                        )))
+                    }
+                }
+            } else {
+                None
+            };
+            cons.push(Constraint::make_ptr(
+                e.text.clone(),
+                &e.property,
+                find,
+                e.min,
+                e.max,
+            ));
+        }
+        for (k, v) in BTreeMap::<&String, &u8>::from_iter(&self.per_group) {
+            // the search and the count are both kingdom piles only here
+            // (check_per_group made sure there are enough)
+            let prop = GroupPrefixProperty::make_ptr(k);
+            let find = col
+                .get_iterators(&prop)
+                .map(|begin| FindPile::make_ptr(col, &begin));
+            let c = Constraint::make_ptr(
+                format!("Exactly {} from {}", v, k),
+                &prop,
+                find,
+                (*v).into(),
+                (*v).into(),
+            );
+            cons.push(c);
+        }
         // now we need to find any keyword interactions
         let mut interacts_kw: BTreeSet<String> = BTreeSet::new();
         for p in col.get_piles() {
@@ -270,80 +478,1451 @@ impl Config {
         }
         Ok(cons)
     }
+
+    // Shuffle, build the constraints and make one selection from the collection.
+    // Also gives back the constraints a --complete kingdom doesn't meet.
+    pub fn make_selection(
+        &mut self,
+        col: CollectionState,
+    ) -> DeckerResult<(SelectionPtr, Vec<DeckerError>)> {
+        let mut col = col;
+        col.shuffle(&mut self.rand);
+        col.apply_weights(&self.weights, &mut self.rand);
+        if self.balance_groups {
+            col.balance_prefixes(&mut self.rand);
+        }
+        // when the --per-group quotas fill the kingdom nothing else fits,
+        // so only search the quota groups. That includes an extra pile
+        // (eg a bane) which would make 11.
+        let quotas: usize = self.per_group.values().map(|v| *v as usize).sum();
+        if quotas == 10 && self.complete.is_none() && self.load_code.is_none() {
+            col.retain_piles(|p| {
+                let in_quota = !(p.get_kingdom() && p.get_supply())
+                    || self
+                        .per_group
+                        .contains_key(&group_name_prefix(p.get_card_group()));
+                in_quota && !needs_extra_pile(p)
+            });
+        }
+        let col = CardCollectionPtr::from_state(col);
+        let constraints = self.build_constraints(&col)?;
+        match (&self.complete, &self.load_code) {
+            (Some(piles), _) => col.complete_selection(piles, &constraints, &mut self.rand),
+            (None, Some(code)) => Ok((
+                col.load_selection(code, &constraints, &mut self.rand)?,
+                vec![],
+            )),
+            (None, None) => Ok((
+                col.generate_selection(
+                    10,
+                    self.optional_extras,
+                    &self.includes,
+                    &constraints,
+                    &mut self.rand,
+                )?,
+                vec![],
+            )),
+        }
+    }
+
+    // --count selections, with --disjoint they share no kingdom piles.
+    // col is the collection for the first one (which the caller may have validated).
+    pub fn make_selections(
+        &mut self,
+        col: CollectionState,
+    ) -> DeckerResult<Vec<(SelectionPtr, Vec<DeckerError>)>> {
+        let mut res = vec![];
+        // piles left for --disjoint
+        let mut piles = self.piles.clone();
+        let mut first = Some(col);
+        for i in 0..self.count {
+            let col = first
+                .take()
+                .unwrap_or_else(|| CardCollectionPtr::new_state(&piles));
+            let (sel, problems) = match self.make_selection(col) {
+                Ok(s) => s,
+                // earlier selections took the piles this one needed
+                Err(DeckerError::SearchExhausted(_)) if i > 0 && self.disjoint => {
+                    return Err(DeckerError::SearchExhausted(format!(
+                        "Ran out of kingdom piles after {} of {} disjoint selections",
+                        i, self.count
+                    )))
+                }
+                Err(e) => return Err(e),
+            };
+            if self.disjoint {
+                for p in sel.get_piles() {
+                    if p.get_supply() && p.get_kingdom() {
+                        piles.remove(p);
+                    }
+                }
+            }
+            res.push((sel, problems));
+        }
+        Ok(res)
+    }
 }
 
-// less need to do reference params to get around multiple ret / error ret
-pub fn load_config(cli: Cli, card_file: String, box_file: String) -> Result<Config, String> {
-    let mut err: String = "".to_string();
+// --exclude-type etc. These only take out piles which get picked (kingdom
+// piles and landscapes) so the support piles for what's left stay.
+#[derive(Clone, Debug)]
+enum PileFilter {
+    Type(String),
+    Keyword(String),
+    Group(String),
+    Cost(String),
+}
 
-    let mut exclude_names =
-        HashMap::from_iter(cli.exclude.iter().map(|name| (name.clone(), false)));
+impl PileFilter {
+    fn check(&self) -> DeckerResult<()> {
+        match self {
+            PileFilter::Cost(c) if c != "debt" && c != "potion" => Err(DeckerError::Config(
+                format!("--exclude-cost takes debt or potion, not {}.", c),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn removes(&self, p: &PilePtr) -> bool {
+        if !(p.get_supply() && p.get_kingdom() || p.is_landscape()) {
+            return false;
+        }
+        match self {
+            PileFilter::Type(t) => p.get_types().contains(t),
+            PileFilter::Keyword(k) => p.get_keywords().contains(k),
+            PileFilter::Group(g) => {
+                p.get_card_group() == g || &group_name_prefix(p.get_card_group()) == g
+            }
+            PileFilter::Cost(c) => p.get_costs().iter().any(|cost| {
+                if c == "debt" {
+                    cost.has_debt()
+                } else {
+                    cost.has_potion()
+                }
+            }),
+        }
+    }
+}
+
+// Support piles which only make sense with a card that was filtered out
+// (eg Bat without Vampire) go too, otherwise validation fails.
+fn drop_orphans(p_set: &mut PileSet) {
+    loop {
+        let card_names: BTreeSet<String> = p_set
+            .iter()
+            .flat_map(|p| p.get_cards().iter().map(|c| c.get_name().to_string()))
+            .collect();
+        let before = p_set.len();
+        p_set.retain(|p| {
+            p.get_supply() && p.get_kingdom()
+                || p.is_landscape()
+                || !p.get_cards().iter().any(|c| {
+                    c.get_other_interactions().iter().any(
+                        |i| matches!(i, Interaction::Card(target) if !card_names.contains(target)),
+                    )
+                })
+        });
+        if p_set.len() == before {
+            return;
+        }
+    }
+}
+
+impl std::fmt::Display for PileFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PileFilter::Type(s) => write!(f, "--exclude-type={}", s),
+            PileFilter::Keyword(s) => write!(f, "--exclude-keyword={}", s),
+            PileFilter::Group(s) => write!(f, "--exclude-group={}", s),
+            PileFilter::Cost(s) => write!(f, "--exclude-cost={}", s),
+        }
+    }
+}
+
+// Where card or box data comes from
+#[derive(Clone)]
+enum DataSource {
+    File(String),
+    Text(String),
+}
+
+impl DataSource {
+    fn read(&self) -> DeckerResult<String> {
+        match self {
+            DataSource::File(fname) => {
+                std::fs::read_to_string(fname).map_err(|e| DeckerError::FileIO {
+                    filename: fname.to_string(),
+                    reason: e.to_string(),
+                })
+            }
+            DataSource::Text(text) => Ok(text.clone()),
+        }
+    }
+
+    // used to label errors
+    fn get_name(&self) -> String {
+        match self {
+            DataSource::File(fname) => fname.to_string(),
+            DataSource::Text(_) => "<in memory data>".to_string(),
+        }
+    }
+}
+
+// Collects everything needed for a generation run.
+// Setters consume and return the builder so calls can be chained:
+//   ConfigBuilder::new().seed(5).groups(&["Seaside"]).build()
+#[derive(Clone)]
+pub struct ConfigBuilder {
+    seed: Option<u64>,
+    bad_rand: bool,
+    cards: DataSource,
+    boxes_data: Option<DataSource>,
+    config_data: Option<DataSource>,
+    boxes: Vec<String>,
+    groups: Vec<String>,
+    excludes: Vec<String>,
+    filters: Vec<PileFilter>,
+    includes: Vec<String>,
+    load_code: Option<String>,
+    complete: Vec<String>,
+    complete_file: Option<String>,
+    vetoes: Vec<String>,
+    count: usize,
+    disjoint: bool,
+    history_file: Option<String>,
+    history_exclude: usize,
+    history_weight: usize,
+    record: bool,
+    weights: Vec<String>,
+    landscape_count: Option<u8>,
+    why: bool,
+    more_info: bool,
+    validate: bool,
+    list_collection: bool,
+    disable_anti_cursors: bool,
+    disable_attack_react: bool,
+    max_cost_repeat: u8,
+    min_types: HashMap<String, u8>,
+    max_types: HashMap<String, u8>,
+    min_keywords: HashMap<String, u8>,
+    max_keywords: HashMap<String, u8>,
+    expressions: Vec<String>,
+    forbidden_pairs: Vec<String>,
+    required_pairs: Vec<String>,
+    min_costs: Vec<String>,
+    max_costs: Vec<String>,
+    per_group: HashMap<String, u8>,
+    balance_groups: bool,
+    max_prefixes: u8,
+}
+
+impl Default for ConfigBuilder {
+    fn default() -> Self {
+        ConfigBuilder::new()
+    }
+}
+
+impl ConfigBuilder {
+    pub fn new() -> ConfigBuilder {
+        ConfigBuilder {
+            seed: None,
+            bad_rand: false,
+            cards: DataSource::File("cards.dat".to_string()),
+            boxes_data: None,
+            config_data: None,
+            boxes: vec![],
+            groups: vec![],
+            excludes: vec![],
+            filters: vec![],
+            includes: vec![],
+            load_code: None,
+            complete: vec![],
+            complete_file: None,
+            vetoes: vec![],
+            count: 1,
+            disjoint: false,
+            history_file: None,
+            history_exclude: 0,
+            history_weight: 0,
+            record: true,
+            weights: vec![],
+            landscape_count: None,
+            why: false,
+            more_info: false,
+            validate: true,
+            list_collection: false,
+            disable_anti_cursors: false,
+            disable_attack_react: false,
+            max_cost_repeat: 0,
+            min_types: HashMap::new(),
+            max_types: HashMap::new(),
+            min_keywords: HashMap::new(),
+            max_keywords: HashMap::new(),
+            expressions: vec![],
+            forbidden_pairs: vec![],
+            required_pairs: vec![],
+            min_costs: vec![],
+            max_costs: vec![],
+            per_group: HashMap::new(),
+            balance_groups: false,
+            max_prefixes: 0,
+        }
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn bad_rand(mut self, v: bool) -> Self {
+        self.bad_rand = v;
+        self
+    }
+
+    pub fn card_file(mut self, fname: &str) -> Self {
+        self.cards = DataSource::File(fname.to_string());
+        self
+    }
+
+    // Card database contents (same format as cards.dat)
+    pub fn card_data(mut self, text: &str) -> Self {
+        self.cards = DataSource::Text(text.to_string());
+        self
+    }
+
+    pub fn box_file(mut self, fname: &str) -> Self {
+        self.boxes_data = Some(DataSource::File(fname.to_string()));
+        self
+    }
+
+    // Box file contents (same format as boxes.dat)
+    pub fn box_data(mut self, text: &str) -> Self {
+        self.boxes_data = Some(DataSource::Text(text.to_string()));
+        self
+    }
+
+    // Settings file, one "setting = value" per line (see apply_config_file)
+    pub fn config_file(mut self, fname: &str) -> Self {
+        self.config_data = Some(DataSource::File(fname.to_string()));
+        self
+    }
+
+    // Settings file contents
+    pub fn config_data(mut self, text: &str) -> Self {
+        self.config_data = Some(DataSource::Text(text.to_string()));
+        self
+    }
+
+    pub fn boxes<S: AsRef<str>>(mut self, names: &[S]) -> Self {
+        self.boxes
+            .extend(names.iter().map(|s| s.as_ref().to_string()));
+        self
+    }
+
+    pub fn groups<S: AsRef<str>>(mut self, names: &[S]) -> Self {
+        self.groups
+            .extend(names.iter().map(|s| s.as_ref().to_string()));
+        self
+    }
+
+    pub fn exclude<S: AsRef<str>>(mut self, names: &[S]) -> Self {
+        self.excludes
+            .extend(names.iter().map(|s| s.as_ref().to_string()));
+        self
+    }
+
+    // Leave out kingdom piles and landscapes with any of these types
+    pub fn exclude_types<S: AsRef<str>>(mut self, names: &[S]) -> Self {
+        self.filters.extend(
+            names
+                .iter()
+                .map(|s| PileFilter::Type(s.as_ref().to_string())),
+        );
+        self
+    }
+
+    pub fn exclude_keywords<S: AsRef<str>>(mut self, names: &[S]) -> Self {
+        self.filters.extend(
+            names
+                .iter()
+                .map(|s| PileFilter::Keyword(s.as_ref().to_string())),
+        );
+        self
+    }
+
+    // Groups or group prefixes (eg Nocturne)
+    pub fn exclude_groups<S: AsRef<str>>(mut self, names: &[S]) -> Self {
+        self.filters.extend(
+            names
+                .iter()
+                .map(|s| PileFilter::Group(s.as_ref().to_string())),
+        );
+        self
+    }
+
+    // "debt" or "potion"
+    pub fn exclude_costs<S: AsRef<str>>(mut self, names: &[S]) -> Self {
+        self.filters.extend(
+            names
+                .iter()
+                .map(|s| PileFilter::Cost(s.as_ref().to_string())),
+        );
+        self
+    }
+
+    pub fn include<S: AsRef<str>>(mut self, names: &[S]) -> Self {
+        self.includes
+            .extend(names.iter().map(|s| s.as_ref().to_string()));
+        self
+    }
+
+    // Kingdom code (see codes.rs) to rebuild instead of generating a selection
+    pub fn load_code(mut self, code: &str) -> Self {
+        self.load_code = Some(code.to_string());
+        self
+    }
+
+    // Kingdom to check and finish (card or pile names)
+    pub fn complete<S: AsRef<str>>(mut self, names: &[S]) -> Self {
+        self.complete
+            .extend(names.iter().map(|s| s.as_ref().to_string()));
+        self
+    }
+
+    // File with more names for complete (one per line or comma separated)
+    pub fn complete_file(mut self, fname: &str) -> Self {
+        self.complete_file = Some(fname.to_string());
+        self
+    }
+
+    // Cards to reroll out of the selection from load_code or complete
+    pub fn veto<S: AsRef<str>>(mut self, names: &[S]) -> Self {
+        self.vetoes
+            .extend(names.iter().map(|s| s.as_ref().to_string()));
+        self
+    }
+
+    // Number of selections to generate
+    pub fn count(mut self, count: usize) -> Self {
+        self.count = count;
+        self
+    }
+
+    // Don't let a kingdom pile appear in more than one of the selections
+    pub fn disjoint(mut self, v: bool) -> Self {
+        self.disjoint = v;
+        self
+    }
+
+    // Play history (see history.rs) which the selections are added to
+    pub fn history_file(mut self, fname: &str) -> Self {
+        self.history_file = Some(fname.to_string());
+        self
+    }
+
+    // Leave out piles from the last `games` kingdoms in the history
+    pub fn history_exclude(mut self, games: usize) -> Self {
+        self.history_exclude = games;
+        self
+    }
+
+    // Make piles from the last `games` kingdoms in the history less likely
+    pub fn history_weight(mut self, games: usize) -> Self {
+        self.history_weight = games;
+        self
+    }
+
+    // Whether to add the selections to the history file
+    pub fn record(mut self, v: bool) -> Self {
+        self.record = v;
+        self
+    }
+
+    // "name:weight" items where name is a card, pile, group or group prefix
+    // and weight scales how likely it is to be picked (1 is normal)
+    pub fn weights<S: AsRef<str>>(mut self, items: &[S]) -> Self {
+        self.weights
+            .extend(items.iter().map(|s| s.as_ref().to_string()));
+        self
+    }
 
-    let card_filename = &cli.cardfile.unwrap_or(card_file);
+    pub fn landscape_count(mut self, count: u8) -> Self {
+        self.landscape_count = Some(count);
+        self
+    }
+
+    pub fn why(mut self, v: bool) -> Self {
+        self.why = v;
+        self
+    }
+
+    pub fn more_info(mut self, v: bool) -> Self {
+        self.more_info = v;
+        self
+    }
+
+    pub fn validate(mut self, v: bool) -> Self {
+        self.validate = v;
+        self
+    }
 
-    let temp_piles = load_cards(card_filename, &mut exclude_names)?;
-    let mut required_groups: HashMap<String, bool> = HashMap::new();
+    pub fn list_collection(mut self, v: bool) -> Self {
+        self.list_collection = v;
+        self
+    }
 
-    if !cli.boxes.is_empty() {
-        let box_filename = cli.boxfile.unwrap_or(box_file);
-        if box_filename.is_empty() {
-            return Err("No box file specified.".to_string());
+    pub fn disable_anti_cursors(mut self, v: bool) -> Self {
+        self.disable_anti_cursors = v;
+        self
+    }
+
+    pub fn disable_attack_react(mut self, v: bool) -> Self {
+        self.disable_attack_react = v;
+        self
+    }
+
+    pub fn max_cost_repeat(mut self, count: u8) -> Self {
+        self.max_cost_repeat = count;
+        self
+    }
+
+    pub fn min_type(mut self, type_name: &str, count: u8) -> Self {
+        self.min_types.insert(type_name.to_string(), count);
+        self
+    }
+
+    pub fn max_type(mut self, type_name: &str, count: u8) -> Self {
+        self.max_types.insert(type_name.to_string(), count);
+        self
+    }
+
+    pub fn min_keyword(mut self, keyword: &str, count: u8) -> Self {
+        self.min_keywords.insert(keyword.to_string(), count);
+        self
+    }
+
+    pub fn max_keyword(mut self, keyword: &str, count: u8) -> Self {
+        self.max_keywords.insert(keyword.to_string(), count);
+        self
+    }
+
+    // "A+B" items (card or pile names) which can't be in a selection together
+    pub fn forbid_together<S: AsRef<str>>(mut self, pairs: &[S]) -> Self {
+        self.forbidden_pairs
+            .extend(pairs.iter().map(|s| s.as_ref().to_string()));
+        self
+    }
+
+    // "A+B" items where picking either one brings in the other
+    pub fn require_together<S: AsRef<str>>(mut self, pairs: &[S]) -> Self {
+        self.required_pairs
+            .extend(pairs.iter().map(|s| s.as_ref().to_string()));
+        self
+    }
+
+    // "band:count" items, eg "2..3:3" for at least three kingdom piles
+    // costing $2 or $3 and "5..:2" for two costing $5 or more
+    pub fn min_costs<S: AsRef<str>>(mut self, items: &[S]) -> Self {
+        self.min_costs
+            .extend(items.iter().map(|s| s.as_ref().to_string()));
+        self
+    }
+
+    // "band:count" items as for min_costs, eg "6..:2" for at most two
+    // kingdom piles costing $6 or more
+    pub fn max_costs<S: AsRef<str>>(mut self, items: &[S]) -> Self {
+        self.max_costs
+            .extend(items.iter().map(|s| s.as_ref().to_string()));
+        self
+    }
+
+    // Constraint written as an expression (see expressions.rs),
+    // eg "count(kw:+buy & !type:Treasure) >= 1"
+    pub fn constraint(mut self, text: &str) -> Self {
+        self.expressions.push(text.to_string());
+        self
+    }
+
+    // Exactly `count` kingdom piles from groups with this prefix
+    pub fn per_group(mut self, prefix: &str, count: u8) -> Self {
+        self.per_group.insert(prefix.to_string(), count);
+        self
+    }
+
+    // Give each expansion (group prefix) the same chance of being picked
+    // instead of each pile
+    pub fn balance_groups(mut self, v: bool) -> Self {
+        self.balance_groups = v;
+        self
+    }
+
+    pub fn max_prefixes(mut self, count: u8) -> Self {
+        self.max_prefixes = count;
+        self
+    }
+
+    pub fn build(self) -> DeckerResult<Config> {
+        if self.config_data.is_some() {
+            return self.apply_config_file()?.build();
         }
+        let card_text = self.cards.read()?;
+        let mut warnings = vec![];
+        // excludes are applied afterwards so the code book sees everything
+        let all_piles = load_cards_from(
+            card_text.as_bytes(),
+            &self.cards.get_name(),
+            &mut HashMap::new(),
+            &mut warnings,
+        )?;
+        let code_book = CodeBook::new(&all_piles);
+        let mut load_code = match &self.load_code {
+            Some(code) => Some(code_book.decode(code)?),
+            None => None,
+        };
+        let named_weights = find_weights(&all_piles, &self.weights)?;
+        let forbidden_pairs = find_pairs(&all_piles, &self.forbidden_pairs)?;
+        let required_pairs = find_pairs(&all_piles, &self.required_pairs)?;
+        let min_costs = parse_cost_bands(&self.min_costs)?;
+        let max_costs = parse_cost_bands(&self.max_costs)?;
+        let mut expressions = vec![];
+        for text in &self.expressions {
+            match parse_constraint(text) {
+                Ok(e) => expressions.push(e),
+                Err((column, message)) => {
+                    return Err(DeckerError::Parse {
+                        source: "--constraint".to_string(),
+                        line: 1,
+                        column: Some(column),
+                        message,
+                    })
+                }
+            }
+        }
+        let all_piles = exclude_piles(all_piles, &self.excludes)?;
+        let mut data_hashes = vec![format!("cards={:016x}", data_hash(card_text.as_bytes()))];
 
-        let box_to_set = read_boxes(&box_filename)?;
-        if box_to_set.is_empty() {
-            return Err("--boxes specified but no boxes known (use --boxfile).".to_string());
+        let (required_groups, box_text) = self.required_groups()?;
+        if let Some(text) = box_text {
+            data_hashes.push(format!("boxes={:016x}", data_hash(text.as_bytes())));
+        }
+        let mut p_set = select_groups(all_piles, required_groups)?;
+        let include_piles = find_includes(&p_set, &self.includes)?;
+        let complete = match self.complete_names()? {
+            names if names.is_empty() => None,
+            _ if load_code.is_some() => {
+                return Err(DeckerError::Config(
+                    "Can't complete a kingdom and load a kingdom code at the same time."
+                        .to_string(),
+                ))
+            }
+            names => Some(find_piles(&p_set, &names)?),
+        };
+        let complete = if self.vetoes.is_empty() {
+            complete
+        } else {
+            // rerolling: the rest of the selection is locked and the vetoed
+            // piles leave the collection so nothing can pick them again
+            let vetoed = find_piles(&p_set, &self.vetoes)?;
+            let mut code = match (load_code, complete) {
+                (Some(code), _) => code,
+                (None, Some(piles)) => KingdomCode::from_piles(&piles),
+                (None, None) => {
+                    return Err(DeckerError::Config(
+                        "--veto needs a selection to reroll (--load-code or --complete)."
+                            .to_string(),
+                    ))
+                }
+            };
+            for v in &vetoed {
+                if !code.piles.iter().any(|(name, _)| name == v.get_name()) {
+                    return Err(DeckerError::Config(format!(
+                        "Can't veto {}, it isn't in the selection.",
+                        v.get_name()
+                    )));
+                }
+            }
+            code.piles
+                .retain(|(name, _)| !vetoed.iter().any(|v| v.get_name() == name));
+            p_set.retain(|p| !vetoed.contains(p));
+            load_code = Some(code);
+            None
         };
 
-        // now we start processing the --boxes param
-        for bp in cli.boxes.iter() {
-            // Can't flatten this into an iterator until we remove early return
-            let set = box_to_set
-                .get(bp)
-                .ok_or(format!("Box {} not known in box file {}", bp, box_filename))?;
-            for name in set {
-                required_groups.insert(name.to_string(), false);
+        // anything asked for by name stays available
+        let mut keep: BTreeSet<&str> = include_piles.iter().map(|p| p.get_name()).collect();
+        keep.extend(complete.iter().flatten().map(|p| p.get_name()));
+        if let Some(code) = &load_code {
+            keep.extend(code.piles.iter().map(|(name, _)| name.as_str()));
+        }
+        let mut filter_counts = vec![];
+        for f in &self.filters {
+            f.check()?;
+            let before = p_set.len();
+            p_set.retain(|p| keep.contains(p.get_name()) || !f.removes(p));
+            drop_orphans(&mut p_set);
+            filter_counts.push((f.to_string(), before - p_set.len()));
+        }
+
+        let mut history_weights = BTreeMap::new();
+        if let Some(fname) = &self.history_file {
+            let history = read_history(fname)?;
+            if let Ok(text) = std::fs::read(fname) {
+                data_hashes.push(format!("history={:016x}", data_hash(&text)));
+            }
+            let recent = recent_piles(&history, self.history_exclude);
+            p_set.retain(|p| keep.contains(p.get_name()) || !recent.contains_key(p.get_name()));
+            // the most recent get the lowest weight
+            let k = self.history_weight as u64;
+            for (name, ago) in recent_piles(&history, self.history_weight) {
+                history_weights.insert(name, 1000 * ago as u64 / (k + 1));
             }
         }
+
+        if self.count != 1 {
+            self.check_count(
+                &p_set,
+                &include_piles,
+                load_code.is_some() || complete.is_some(),
+            )?;
+        }
+
+        // now we set up randomiser
+        // The cap here is arbitrary (want it to be at least as big
+        // as 3 x pile size for shuffling
+        let rand_kind = if self.bad_rand {
+            RandKind::Bad
+        } else {
+            RandKind::Xoshiro
+        };
+        // unseeded runs get a fresh seed (kept to 32 bits so it's easy to copy)
+        let seed = self.seed.unwrap_or_else(|| OsRng.next_u32().into());
+        let mut rand = get_rand_stream(seed, (p_set.len() as u64).saturating_mul(10), rand_kind);
+
+        // the prefixes were a random choice so stick to the ones the code uses
+        let p_set = match &load_code {
+            Some(code) if self.max_prefixes > 0 => code_prefixes(p_set, code),
+            Some(_) => p_set,
+            None => limit_prefixes(
+                p_set,
+                &include_piles,
+                &self.per_group,
+                self.max_prefixes,
+                &mut rand,
+            )?,
+        };
+        let minimums = self.minimums(&p_set, &min_costs, &expressions);
+        self.check_per_group(&p_set, &include_piles, &minimums)?;
+        if load_code.is_none() && complete.is_none() {
+            warnings.extend(extra_pile_warning(&p_set, &self.per_group));
+        }
+
+        let weights = pile_weights(&p_set, &named_weights, &history_weights);
+
+        // Now let's work out how many optional extras we need
+        let opt_extra = self.landscape_count.unwrap_or_else(|| {
+            let x = (rand.gen::<u64>() % 7) as u8;
+            if x < 3 {
+                x
+            } else {
+                0
+            }
+        });
+
+        let options = self.get_options_string(seed);
+        let header = format!(
+            "Options: {} # rng={} {}",
+            options,
+            rand_kind.get_name(),
+            data_hashes.join(" ")
+        );
+
+        Ok(Config {
+            rand,
+            rand_kind,
+            seed,
+            options,
+            header,
+            why: self.why,
+            more_info: self.more_info,
+            optional_extras: opt_extra,
+            validate: self.validate,
+            list_collection: self.list_collection,
+            disable_anti_cursors: self.disable_anti_cursors,
+            disable_attack_react: self.disable_attack_react,
+            max_cost_repeat: self.max_cost_repeat,
+            min_types: self.min_types,
+            max_types: self.max_types,
+            min_keywords: self.min_keywords,
+            max_keywords: self.max_keywords,
+            min_costs,
+            max_costs,
+            forbidden_pairs,
+            required_pairs,
+            expressions,
+            per_group: self.per_group,
+            balance_groups: self.balance_groups,
+            piles: p_set,
+            includes: include_piles,
+            code_book,
+            load_code,
+            complete,
+            count: self.count,
+            disjoint: self.disjoint,
+            weights,
+            history_file: self.history_file.filter(|_| self.record),
+            filter_counts,
+            warnings,
+        })
     }
 
-    for group_name in cli.groups {
-        required_groups.insert(group_name.to_string(), false);
+    // Command line options (in a fixed order) which will reproduce
+    // the same selection given the same data files
+    pub fn get_options_string(&self, seed: u64) -> String {
+        let sorted = |v: &Vec<String>| {
+            let s = BTreeSet::<&String>::from_iter(v);
+            Vec::from_iter(s.into_iter().map(|x| x.as_str())).join(",")
+        };
+        let type_counts = |m: &HashMap<String, u8>| {
+            let s = BTreeMap::<&String, &u8>::from_iter(m);
+            Vec::from_iter(s.into_iter().map(|(k, v)| format!("{}:{}", k, v))).join(",")
+        };
+        let mut opts = vec![format!("--seed={}", seed)];
+        if self.bad_rand {
+            opts.push("--badrand".to_string());
+        }
+        if let DataSource::File(fname) = &self.cards {
+            if fname != "cards.dat" {
+                opts.push(format!("--cardfile={}", fname));
+            }
+        }
+        if !self.boxes.is_empty() {
+            if let Some(DataSource::File(fname)) = &self.boxes_data {
+                opts.push(format!("--boxfile={}", fname));
+            }
+            opts.push(format!("--boxes={}", sorted(&self.boxes)));
+        }
+        if !self.groups.is_empty() {
+            opts.push(format!("--groups={}", sorted(&self.groups)));
+        }
+        if !self.excludes.is_empty() {
+            opts.push(format!("--exclude={}", sorted(&self.excludes)));
+        }
+        for f in &self.filters {
+            opts.push(f.to_string());
+        }
+        if !self.includes.is_empty() {
+            opts.push(format!("--include={}", sorted(&self.includes)));
+        }
+        if let Some(code) = &self.load_code {
+            opts.push(format!("--load-code={}", code));
+        }
+        if !self.complete.is_empty() {
+            opts.push(format!("--complete={}", sorted(&self.complete)));
+        }
+        if let Some(fname) = &self.complete_file {
+            opts.push(format!("--complete-file={}", fname));
+        }
+        if !self.vetoes.is_empty() {
+            opts.push(format!("--veto={}", sorted(&self.vetoes)));
+        }
+        if self.count != 1 {
+            opts.push(format!("--count={}", self.count));
+        }
+        if self.disjoint {
+            opts.push("--disjoint".to_string());
+        }
+        if !self.weights.is_empty() {
+            opts.push(format!("--weight={}", sorted(&self.weights)));
+        }
+        if let Some(fname) = &self.history_file {
+            opts.push(format!("--history={}", fname));
+        }
+        if !self.record {
+            opts.push("--no-record".to_string());
+        }
+        if self.history_exclude > 0 {
+            opts.push(format!("--history-exclude={}", self.history_exclude));
+        }
+        if self.history_weight > 0 {
+            opts.push(format!("--history-weight={}", self.history_weight));
+        }
+        if let Some(count) = self.landscape_count {
+            opts.push(format!("--landscape-count={}", count));
+        }
+        if self.disable_attack_react {
+            opts.push("--no-attack-react".to_string());
+        }
+        if self.disable_anti_cursors {
+            opts.push("--no-anti-cursor".to_string());
+        }
+        if self.max_cost_repeat > 0 {
+            opts.push(format!("--max-cost-repeat={}", self.max_cost_repeat));
+        }
+        if !self.min_types.is_empty() {
+            opts.push(format!("--min-type={}", type_counts(&self.min_types)));
+        }
+        if !self.max_types.is_empty() {
+            opts.push(format!("--max-type={}", type_counts(&self.max_types)));
+        }
+        if !self.min_keywords.is_empty() {
+            opts.push(format!("--min-keyword={}", type_counts(&self.min_keywords)));
+        }
+        if !self.max_keywords.is_empty() {
+            opts.push(format!("--max-keyword={}", type_counts(&self.max_keywords)));
+        }
+        if !self.forbidden_pairs.is_empty() {
+            opts.push(format!(
+                "--forbid-together={}",
+                self.forbidden_pairs.join(",")
+            ));
+        }
+        if !self.required_pairs.is_empty() {
+            opts.push(format!(
+                "--require-together={}",
+                self.required_pairs.join(",")
+            ));
+        }
+        if !self.min_costs.is_empty() {
+            opts.push(format!("--min-cost={}", self.min_costs.join(",")));
+        }
+        if !self.max_costs.is_empty() {
+            opts.push(format!("--max-cost={}", self.max_costs.join(",")));
+        }
+        for text in &self.expressions {
+            // quoted as these have spaces and & | ! in them
+            opts.push(format!("--constraint='{}'", text));
+        }
+        if !self.per_group.is_empty() {
+            opts.push(format!("--per-group={}", type_counts(&self.per_group)));
+        }
+        if self.balance_groups {
+            opts.push("--balance-groups".to_string());
+        }
+        if self.max_prefixes > 0 {
+            opts.push(format!("--max-prefixes={}", self.max_prefixes));
+        }
+        opts.join(" ")
     }
 
-    let mut p_set = PileSet::new();
-    if !required_groups.is_empty() {
-        required_groups.insert("base".to_string(), false); // avoid invalid games
-        let mut to_drop: Vec<String> = vec![];
-        for pile in temp_piles {
-            let group_name = pile.get_card_group().to_string();
-            match required_groups.get_mut(&group_name) {
-                Some(g) => {
-                    p_set.insert(pile.clone());
-                    *g = true;
+    // The options asking for at least some kingdom piles with a property,
+    // as (option, property, count)
+    fn minimums(
+        &self,
+        p_set: &PileSet,
+        min_costs: &[(CostBand, u8)],
+        expressions: &[ConstraintExpr],
+    ) -> Vec<(String, PropertyPtr, u64)> {
+        let piles = Vec::from_iter(p_set.iter().cloned());
+        let mut res = vec![];
+        for (t, count) in &self.min_types {
+            res.push((
+                format!("--min-type={}:{}", t, count),
+                TypeProperty::make_ptr(t, true),
+                (*count).into(),
+            ));
+        }
+        for (kw, count) in BTreeMap::<&String, &u8>::from_iter(&self.min_keywords) {
+            res.push((
+                format!("--min-keyword={}:{}", kw, count),
+                KeywordProperty::make_ptr(kw, true),
+                (*count).into(),
+            ));
+        }
+        for (band, count) in min_costs {
+            res.push((
+                format!("--min-cost={}:{}", band.label(), count),
+                CostProperty::make_ptr_set(band.costs_from(&piles), true),
+                (*count).into(),
+            ));
+        }
+        for e in expressions.iter().filter(|e| e.min > 0) {
+            res.push((e.text.clone(), e.property.clone(), e.min));
+        }
+        res
+    }
+
+    // Are there enough piles for the --per-group quotas, and room for the
+    // included piles and the minimums?
+    fn check_per_group(
+        &self,
+        p_set: &PileSet,
+        includes: &PileSet,
+        minimums: &[(String, PropertyPtr, u64)],
+    ) -> DeckerResult<()> {
+        let total: usize = self.per_group.values().map(|v| *v as usize).sum();
+        if total > 10 {
+            return Err(DeckerError::Config(format!(
+                "--per-group asks for {} kingdom piles but a selection only has 10.",
+                total
+            )));
+        }
+        let kingdom = |p: &&PilePtr| p.get_kingdom() && p.get_supply();
+        let quota_group = |p: &&PilePtr| {
+            self.per_group
+                .contains_key(&group_name_prefix(p.get_card_group()))
+        };
+        let others = includes
+            .iter()
+            .filter(kingdom)
+            .filter(|p| !quota_group(p))
+            .count();
+        if total + others > 10 {
+            return Err(DeckerError::Config(format!(
+                "--per-group asks for {} kingdom piles which leaves no room for {} included from other groups.",
+                total, others
+            )));
+        }
+        for (prefix, count) in BTreeMap::<&String, &u8>::from_iter(&self.per_group) {
+            let included = includes
+                .iter()
+                .filter(kingdom)
+                .filter(|p| &group_name_prefix(p.get_card_group()) == prefix)
+                .count();
+            if included > *count as usize {
+                return Err(DeckerError::Config(format!(
+                    "--per-group wants {} from {} but {} are included.",
+                    count, prefix, included
+                )));
+            }
+        }
+        // when the quotas fill the kingdom the minimums come from them too
+        // (and nothing can bring an 11th pile)
+        if total == 10 {
+            if let Some(p) = includes.iter().find(|p| needs_extra_pile(p)) {
+                return Err(DeckerError::Config(format!(
+                    "--per-group fills the kingdom so there is no room for the extra pile {} needs.",
+                    p.get_name()
+                )));
+            }
+            for (option, prop, count) in minimums {
+                let available = p_set
+                    .iter()
+                    .filter(kingdom)
+                    .filter(quota_group)
+                    .filter(|p| !needs_extra_pile(p))
+                    .filter(|p| prop.pile_meets(p))
+                    .count();
+                if (available as u64) < *count {
+                    return Err(DeckerError::Config(format!(
+                        "--per-group fills the kingdom but its groups only have {} piles for {}.",
+                        available, option
+                    )));
                 }
-                None => to_drop.push(group_name.clone()),
+            }
+        }
+        for (prefix, count) in BTreeMap::<&String, &u8>::from_iter(&self.per_group) {
+            if *count == 0 {
+                continue;
+            }
+            let available = p_set
+                .iter()
+                .filter(|p| {
+                    p.get_kingdom()
+                        && p.get_supply()
+                        && &group_name_prefix(p.get_card_group()) == prefix
+                })
+                .count();
+            if available == 0 {
+                return Err(DeckerError::UnknownGroup(prefix.to_string()));
+            }
+            if available < *count as usize {
+                return Err(DeckerError::Config(format!(
+                    "--per-group wants {} from {} but the collection only has {}.",
+                    count, prefix, available
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    // Can we make that many selections?
+    fn check_count(&self, p_set: &PileSet, includes: &PileSet, fixed: bool) -> DeckerResult<()> {
+        if self.count == 0 {
+            return Err(DeckerError::Config(
+                "--count must be at least 1.".to_string(),
+            ));
+        }
+        if fixed {
+            return Err(DeckerError::Config(
+                "--count only works when generating selections (not with --load-code, --complete or --veto)."
+                    .to_string(),
+            ));
+        }
+        if !self.disjoint {
+            return Ok(());
+        }
+        if !includes.is_empty() {
+            return Err(DeckerError::Config(
+                "--include would put the same cards in every selection so can't be used with --disjoint."
+                    .to_string(),
+            ));
+        }
+        let kingdom = p_set
+            .iter()
+            .filter(|p| p.get_supply() && p.get_kingdom())
+            .count();
+        if kingdom < 10 * self.count {
+            return Err(DeckerError::Config(format!(
+                "{} disjoint selections need at least {} kingdom piles but the collection only has {}.",
+                self.count,
+                10 * self.count,
+                kingdom
+            )));
+        }
+        Ok(())
+    }
+
+    // Settings files use the names of the command line options (without
+    // the --), eg
+    //   # we like Seaside
+    //   weight = Seaside:2, Rats:0.1
+    //   per-group = Guilds:2
+    //   min-keyword = +buy:1
+    //   min-cost = 2..3:3, 5..:2
+    //   forbid-together = Witch+Mountebank
+    //   exclude-type = Night
+    //   constraint = count(type:Action & kw:+action & !type:Attack) >= 2
+    // Anything in the file is added to what was set already. The options
+    // line then lists it all so the file isn't needed to reproduce a run.
+    fn apply_config_file(mut self) -> DeckerResult<Self> {
+        let data = match self.config_data.take() {
+            Some(d) => d,
+            None => return Ok(self),
+        };
+        let text = data.read()?;
+        for (num, raw) in text.lines().enumerate() {
+            let line = raw.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let bad_at = |column: Option<usize>, message: String| DeckerError::Parse {
+                source: data.get_name(),
+                line: num + 1,
+                column,
+                message,
             };
+            let bad = |message: String| bad_at(None, message);
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| bad("expected setting = value".to_string()))?;
+            let items: Vec<&str> = value
+                .split(',')
+                .map(|s| s.trim())
+                .filter(|s| !s.is_empty())
+                .collect();
+            let key = key.trim();
+            self = match key {
+                "weight" => self.weights(&items),
+                "min-cost" => self.min_costs(&items),
+                "forbid-together" => self.forbid_together(&items),
+                "exclude-type" => self.exclude_types(&items),
+                "exclude-keyword" => self.exclude_keywords(&items),
+                "exclude-group" => self.exclude_groups(&items),
+                "exclude-cost" => self.exclude_costs(&items),
+                "require-together" => self.require_together(&items),
+                "max-cost" => self.max_costs(&items),
+                // the whole value, commas and all
+                "constraint" => {
+                    let expr = value.trim();
+                    if let Err((column, message)) = parse_constraint(expr) {
+                        let before = raw.len() - value.trim_start().len();
+                        return Err(bad_at(
+                            Some(raw[..before].chars().count() + column),
+                            message,
+                        ));
+                    }
+                    self.constraint(expr)
+                }
+                "min-type" | "max-type" | "min-keyword" | "max-keyword" | "per-group" => {
+                    for item in items {
+                        match item
+                            .split_once(':')
+                            .map(|(k, v)| (k.trim(), v.trim().parse::<u8>()))
+                        {
+                            Some((name, Ok(v))) if !name.is_empty() => {
+                                self = match key {
+                                    "min-type" => self.min_type(name, v),
+                                    "max-type" => self.max_type(name, v),
+                                    "min-keyword" => self.min_keyword(name, v),
+                                    "max-keyword" => self.max_keyword(name, v),
+                                    _ => self.per_group(name, v),
+                                }
+                            }
+                            _ => return Err(bad(format!("expected name:count not {}", item))),
+                        }
+                    }
+                    self
+                }
+                k => return Err(bad(format!("unknown setting {}", k))),
+            };
+        }
+        Ok(self)
+    }
+
+    // --complete plus whatever is in --complete-file
+    fn complete_names(&self) -> DeckerResult<Vec<String>> {
+        let mut names = self.complete.clone();
+        if let Some(fname) = &self.complete_file {
+            let text = DataSource::File(fname.to_string()).read()?;
+            for line in text.lines() {
+                if line.trim_start().starts_with('#') {
+                    continue;
+                }
+                names.extend(
+                    line.split(',')
+                        .map(|s| s.trim())
+                        .filter(|s| !s.is_empty())
+                        .map(|s| s.to_string()),
+                );
+            }
         }
-        // now we check to see if all groups are known
-        for (n, b) in required_groups {
-            if !b {
-                if !err.is_empty() {
-                    err += "\n";
+        Ok(names)
+    }
+
+    // Turn --boxes and --groups into the set of groups the collection
+    // is restricted to (empty means no restriction).
+    // Also hands back the box data if it was needed.
+    fn required_groups(&self) -> DeckerResult<(BTreeSet<String>, Option<String>)> {
+        let mut required_groups = BTreeSet::new();
+        let mut box_text = None;
+        if !self.boxes.is_empty() {
+            let (box_source, data) = match &self.boxes_data {
+                None => return Err(DeckerError::Config("No box file specified.".to_string())),
+                Some(DataSource::File(fname)) if fname.is_empty() => {
+                    return Err(DeckerError::Config("No box file specified.".to_string()));
                 }
-                err = format!("{}Unknown group {}", err, n);
+                Some(d) => (d.get_name(), d),
             };
+            let text = data.read()?;
+            let box_to_set = parse_boxes(text.as_bytes(), &box_source)?;
+            box_text = Some(text);
+            if box_to_set.is_empty() {
+                return Err(DeckerError::Config(
+                    "--boxes specified but no boxes known (use --boxfile).".to_string(),
+                ));
+            };
+
+            // now we start processing the --boxes param
+            for bp in self.boxes.iter() {
+                // Can't flatten this into an iterator until we remove early return
+                let set = box_to_set.get(bp).ok_or(DeckerError::UnknownBox {
+                    name: bp.to_string(),
+                    source: box_source.to_string(),
+                })?;
+                for name in set {
+                    required_groups.insert(name.to_string());
+                }
+            }
         }
-    } else {
-        for p in temp_piles {
-            p_set.insert(p); // add all piles
+        for group_name in &self.groups {
+            required_groups.insert(group_name.to_string());
+        }
+        Ok((required_groups, box_text))
+    }
+}
+
+fn select_groups(
+    all_piles: Vec<PilePtr>,
+    required_groups: BTreeSet<String>,
+) -> DeckerResult<PileSet> {
+    if required_groups.is_empty() {
+        return Ok(PileSet::from_iter(all_piles)); // add all piles
+    }
+    let mut required_groups =
+        HashMap::<String, bool>::from_iter(required_groups.into_iter().map(|g| (g, false)));
+    required_groups.insert("base".to_string(), false); // avoid invalid games
+    let mut p_set = PileSet::new();
+    for pile in all_piles {
+        if let Some(g) = required_groups.get_mut(pile.get_card_group()) {
+            p_set.insert(pile.clone());
+            *g = true;
+        };
+    }
+    // now we check to see if all groups are known
+    let mut unknown = BTreeSet::new();
+    for (n, b) in required_groups {
+        if !b {
+            unknown.insert(n);
         }
+    }
+    match DeckerError::from_list(Vec::from_iter(
+        unknown.into_iter().map(DeckerError::UnknownGroup),
+    )) {
+        Some(e) => Err(e),
+        None => Ok(p_set),
+    }
+}
+
+// Like find_includes but pile names (eg Castles) are fine too
+// and every unknown name is reported
+fn find_piles(p_set: &PileSet, names: &[String]) -> DeckerResult<PileSet> {
+    let mut res = PileSet::new();
+    let mut unknown = vec![];
+    for name in names {
+        let found = p_set
+            .iter()
+            .find(|p| p.get_name() == name || p.get_cards().iter().any(|c| c.get_name() == name));
+        match found {
+            Some(p) => {
+                res.insert(p.clone());
+            }
+            None => unknown.push(DeckerError::UnknownCard(name.to_string())),
+        }
+    }
+    match DeckerError::from_list(unknown) {
+        Some(e) => Err(e),
+        None => Ok(res),
+    }
+}
+
+// "A+B" items to pairs of pile names. Some pile names have + in them
+// (eg Catapult+Rocks) so use the first split where both halves are known.
+fn find_pairs(all_piles: &[PilePtr], items: &[String]) -> DeckerResult<Vec<(String, String)>> {
+    let pile_name = |name: &str| {
+        let name = name.trim();
+        all_piles
+            .iter()
+            .find(|p| p.get_name() == name || p.get_cards().iter().any(|c| c.get_name() == name))
+            .map(|p| p.get_name().to_string())
     };
+    let mut res = vec![];
+    for item in items {
+        let pair = item
+            .match_indices('+')
+            .find_map(|(i, _)| Some((pile_name(&item[..i])?, pile_name(&item[i + 1..])?)));
+        match pair {
+            Some((a, b)) if a != b => res.push((a, b)),
+            _ => {
+                return Err(DeckerError::Config(format!(
+                    "Can't find two different cards in {} (expected eg Witch+Moat).",
+                    item
+                )))
+            }
+        }
+    }
+    Ok(res)
+}
 
+// "band:count" items (see CostBand::parse)
+fn parse_cost_bands(items: &[String]) -> DeckerResult<Vec<(CostBand, u8)>> {
+    let mut res = vec![];
+    for item in items {
+        let band = item.rsplit_once(':').and_then(|(band, count)| {
+            Some((CostBand::parse(band)?, count.trim().parse::<u8>().ok()?))
+        });
+        match band {
+            Some(b) => res.push(b),
+            None => {
+                return Err(DeckerError::Config(format!(
+                    "Can't understand cost band {} (expected eg 2..3:3, 5..:2 or 4:1).",
+                    item
+                )))
+            }
+        }
+    }
+    Ok(res)
+}
+
+fn weight_applies(pile: &PilePtr, name: &str) -> bool {
+    pile.get_name() == name
+        || pile.get_cards().iter().any(|c| c.get_name() == name)
+        || pile.get_card_group() == name
+        || group_name_prefix(pile.get_card_group()) == name
+}
+
+// Check "name:weight" items against the whole database (so a settings file
+// still works with --groups) and convert weights to thousandths
+fn find_weights(all_piles: &[PilePtr], items: &[String]) -> DeckerResult<Vec<(String, u64)>> {
+    let mut res = vec![];
+    let mut unknown = vec![];
+    for item in items {
+        let parsed = item.rsplit_once(':').and_then(|(name, w)| {
+            let w = w.trim().parse::<f64>().ok()?;
+            if (0.0..=1000.0).contains(&w) {
+                Some((name.trim().to_string(), (w * 1000.0).round() as u64))
+            } else {
+                None
+            }
+        });
+        let (name, w) = parsed.ok_or_else(|| {
+            DeckerError::Config(format!(
+                "Can't understand weight {} (expected name:weight eg Seaside:2 or Rats:0.25).",
+                item
+            ))
+        })?;
+        if !all_piles.iter().any(|p| weight_applies(p, &name)) {
+            unknown.push(DeckerError::UnknownCard(name.clone()));
+        }
+        res.push((name, w));
+    }
+    match DeckerError::from_list(unknown) {
+        Some(e) => Err(e),
+        None => Ok(res),
+    }
+}
+
+// Combined weight (thousandths) for each pile, every weight which applies
+// multiplies in. Empty if there are no weights so the order isn't touched.
+fn pile_weights(
+    p_set: &PileSet,
+    named: &[(String, u64)],
+    history: &BTreeMap<String, u64>,
+) -> BTreeMap<String, u64> {
+    let mut res = BTreeMap::new();
+    if named.is_empty() && history.is_empty() {
+        return res;
+    }
+    for p in p_set {
+        let mut w = 1000;
+        for (name, v) in named {
+            if weight_applies(p, name) {
+                w = w * v / 1000;
+            }
+        }
+        if let Some(v) = history.get(p.get_name()) {
+            w = w * v / 1000;
+        }
+        res.insert(p.get_name().to_string(), w);
+    }
+    res
+}
+
+// Drop the piles holding any of the named cards
+fn exclude_piles(all_piles: Vec<PilePtr>, names: &[String]) -> DeckerResult<Vec<PilePtr>> {
+    let mut exclude_names =
+        HashMap::<&str, bool>::from_iter(names.iter().map(|name| (name.as_str(), false)));
+    let mut res = vec![];
+    for pile in all_piles {
+        let mut keep = true;
+        for card in pile.get_cards() {
+            if let Some(v) = exclude_names.get_mut(card.get_name()) {
+                *v = true;
+                keep = false;
+            }
+        }
+        if keep {
+            res.push(pile);
+        }
+    }
+    let unknown = BTreeSet::from_iter(
+        exclude_names
+            .into_iter()
+            .filter(|(_, found)| !found)
+            .map(|(name, _)| name.to_string()),
+    );
+    match DeckerError::from_list(Vec::from_iter(
+        unknown.into_iter().map(DeckerError::UnknownCard),
+    )) {
+        Some(e) => Err(e),
+        None => Ok(res),
+    }
+}
+
+fn find_includes(p_set: &PileSet, names: &[String]) -> DeckerResult<PileSet> {
     let mut include_piles: PileSet = PileSet::new();
-    for name in cli.include {
+    for name in names {
         let mut found = false;
-        'overset: for pile in &p_set {
+        'overset: for pile in p_set {
             for card in pile.get_cards() {
                 if card.get_name() == name {
                     include_piles.insert(pile.clone());
@@ -353,136 +1932,565 @@ pub fn load_config(cli: Cli, card_file: String, box_file: String) -> Result<Conf
             }
         }
         if !found {
-            return Err(format!("Can't find card {}", name));
+            return Err(DeckerError::UnknownCard(name.to_string()));
         }
     }
+    Ok(include_piles)
+}
 
-    let mut min_types: HashMap<String, u8> = HashMap::new();
-    for s in cli.min_type.iter() {
-        // Checking for valid inputs of form "Type:Int"
-        match s.split_once(':') {
-            Some((lhs, rhs)) if !lhs.is_empty() => {
-                let typecount = rhs.parse::<u8>().unwrap_or(0);
-                min_types.insert(lhs.to_string(), typecount);
-            }
-            _ => continue,
+// Now we limit the groups we can draw from.
+// We'll do it here before anything gets into the
+// CardCollection object.
+// We need to take into account which prefixes any
+// --include cards force in (and also if that exceeds
+// the limit
+fn limit_prefixes(
+    p_set: PileSet,
+    include_piles: &PileSet,
+    per_group: &HashMap<String, u8>,
+    max_prefixes: u8,
+    rand: &mut Box<dyn RngCore>,
+) -> DeckerResult<PileSet> {
+    if max_prefixes == 0 {
+        return Ok(p_set);
+    }
+    let suggested_max = max_prefixes + 1;
+    let mut chosen_prefixes = HashSet::<String>::new();
+    chosen_prefixes.insert("base".to_string());
+    for ip in include_piles {
+        chosen_prefixes.insert(group_name_prefix(ip.get_card_group()));
+    }
+    for (prefix, count) in per_group {
+        if *count > 0 {
+            chosen_prefixes.insert(prefix.to_string());
+        }
+    }
+    if chosen_prefixes.len() > (suggested_max as usize) {
+        // need to -1 from both numbers because of hidden "base" group
+        return Err(DeckerError::Config(format!(
+            "Requested at most {} big groups, but included cards and --per-group are drawn from {}.",
+            suggested_max - 1,
+            chosen_prefixes.len() - 1
+        )));
+    };
+    let mut group_prefixes = BTreeSet::<String>::new();
+    for p in &p_set {
+        group_prefixes.insert(group_name_prefix(p.get_card_group()));
+    }
+    // now shuffle the prefixes
+    let mut shuffle_prefixes = Vec::from_iter(group_prefixes);
+    let n_sets = shuffle_prefixes.len();
+    // wow, could have named these better
+    for _ in 0..3 {
+        for i in (1..n_sets).rev() {
+            let pos = rand.gen::<usize>() % i;
+            shuffle_prefixes.swap(i, pos);
+        }
+    }
+    let mut i = 0;
+    while i < n_sets && chosen_prefixes.len() < (suggested_max as usize) {
+        chosen_prefixes.insert(shuffle_prefixes[i].to_string());
+        i += 1;
+    }
+    let mut new_files = PileSet::new();
+    for p2 in p_set {
+        if chosen_prefixes.contains(&group_name_prefix(p2.get_card_group())) {
+            new_files.insert(p2.clone());
         }
     }
+    Ok(new_files)
+}
 
-    let mut max_types: HashMap<String, u8> = HashMap::new();
-    for s in cli.max_type.iter() {
-        match s.split_once(':') {
-            Some((lhs, rhs)) if !lhs.is_empty() => {
-                let typecount = rhs.parse::<u8>().unwrap_or(0);
-                max_types.insert(lhs.to_string(), typecount);
-            }
-            _ => continue,
+fn code_prefixes(p_set: PileSet, code: &KingdomCode) -> PileSet {
+    let mut chosen_prefixes = HashSet::<String>::new();
+    chosen_prefixes.insert("base".to_string());
+    for p in &p_set {
+        if code.piles.iter().any(|(name, _)| name == p.get_name()) {
+            chosen_prefixes.insert(group_name_prefix(p.get_card_group()));
         }
     }
+    PileSet::from_iter(
+        p_set
+            .into_iter()
+            .filter(|p| chosen_prefixes.contains(&group_name_prefix(p.get_card_group()))),
+    )
+}
 
-    let use_bad_rand = cli.badrand;
-    // TODO: Let the seed be picked from the randomiser if not provided
-    // TODO: Find a way to update the CLI for seed value instead of using args
-    let seed = cli.seed.unwrap_or(0);
-    let max_cost_repeat = cli.max_cost_repeat;
-    let validate = !cli.no_validate;
-    let list_collection = cli.list;
+// Does p bring an extra kingdom pile (eg Young Witch's bane)?
+fn needs_extra_pile(p: &PilePtr) -> bool {
+    p.get_other_interactions()
+        .iter()
+        .any(|i| matches!(i, Interaction::Extra { .. }))
+}
 
-    // now we set up randomiser
-    // The cap here is arbitrary (want it to be at least as big
-    // as 3 x pile size for shuffling
-    let mut rand = get_rand_stream(seed, (p_set.len() as u64).saturating_mul(10), use_bad_rand);
+// When the --per-group quotas fill the kingdom the piles which need an
+// extra pile are left out (see Config::make_selection), so say which
+fn extra_pile_warning(p_set: &PileSet, per_group: &HashMap<String, u8>) -> Option<String> {
+    let total: usize = per_group.values().map(|v| *v as usize).sum();
+    if total < 10 {
+        return None;
+    }
+    let names: Vec<&str> = p_set
+        .iter()
+        .filter(|p| {
+            !(p.get_kingdom() && p.get_supply())
+                || per_group.contains_key(&group_name_prefix(p.get_card_group()))
+        })
+        .filter(|p| needs_extra_pile(p))
+        .map(|p| p.get_name())
+        .collect();
+    if names.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "--per-group fills the kingdom so {} can't be picked (no room for an extra pile).",
+        names.join(", ")
+    ))
+}
+
+#[cfg(test)]
+mod test_builder {
+    use super::*;
+
+    const CARDS: &str = "\
+Card title,Pile title,Group,in supply,is kingdom,types,cost,spending power,debt,potion cost,Points,keywords,interacts with keywords,other interacts,comments
+Copper,,base,y,n,Treasure,0,1,,,,,,,
+Village,,Core,y,y,Action,3,,,,,+action,,,
+Smithy,,Core,y,y,Action,4,,,,,+card,,,
+Lighthouse,,Seaside,y,y,Action;Duration,2,,,,,+action,,,
+";
 
-    // Now we limit the groups we can draw from.
-    // We'll do it here before anything gets into the
-    // CardCollection object.
-    // We need to take into account which prefixes any
-    // --include cards force in (and also if that exceeds
-    // the limit
+    const BOXES: &str = "Core=Core\nSeaside=Seaside\n";
 
-    let mut suggested_max = cli.max_prefixes;
-    if suggested_max > 0 {
-        suggested_max += 1;
-        let mut chosen_prefixes = HashSet::<String>::new();
-        chosen_prefixes.insert("base".to_string());
-        for ip in &include_piles {
-            chosen_prefixes.insert(group_name_prefix(ip.get_card_group()));
+    #[test]
+    fn test_in_memory_data() {
+        let conf = ConfigBuilder::new()
+            .card_data(CARDS)
+            .box_data(BOXES)
+            .boxes(&["Seaside"])
+            .include(&["Lighthouse"])
+            .build()
+            .unwrap();
+        let names: Vec<&str> = conf.piles.iter().map(|p| p.get_name()).collect();
+        assert_eq!(names, vec!["Copper", "Lighthouse"]);
+        assert_eq!(conf.includes.len(), 1);
+    }
+
+    #[test]
+    fn test_unknown_box() {
+        let res = ConfigBuilder::new()
+            .card_data(CARDS)
+            .box_data(BOXES)
+            .boxes(&["Prosperity"])
+            .build();
+        match res {
+            Err(DeckerError::UnknownBox { name, .. }) => assert_eq!(name, "Prosperity"),
+            _ => panic!("expected unknown box"),
         }
-        if chosen_prefixes.len() > (suggested_max as usize) {
-            // need to -1 from both numbers because of hidden "base" group
-            return Err(format!(
-                "Requested at most {} big groups, but included cards are drawn from {}.",
-                suggested_max - 1,
-                chosen_prefixes.len() - 1
-            ));
-        };
-        let mut group_prefixes = BTreeSet::<String>::new();
-        for p in &p_set {
-            group_prefixes.insert(group_name_prefix(p.get_card_group()));
+    }
+    #[test]
+    fn test_weights() {
+        let conf = ConfigBuilder::new()
+            .card_data(CARDS)
+            .config_data("# comment\nweight = Core:0.5, Smithy:3\n")
+            .weights(&["Seaside:2"])
+            .build()
+            .unwrap();
+        assert_eq!(conf.weights.get("Smithy"), Some(&1500));
+        assert_eq!(conf.weights.get("Village"), Some(&500));
+        assert_eq!(conf.weights.get("Lighthouse"), Some(&2000));
+        assert_eq!(conf.weights.get("Copper"), Some(&1000));
+        assert!(conf
+            .options
+            .contains("--weight=Core:0.5,Seaside:2,Smithy:3"));
+        let res = ConfigBuilder::new()
+            .card_data(CARDS)
+            .config_data("weight = Rats:0.1\n")
+            .build();
+        assert_eq!(
+            res.err(),
+            Some(DeckerError::UnknownCard("Rats".to_string()))
+        );
+        let res = ConfigBuilder::new()
+            .card_data(CARDS)
+            .config_data("wieght = Smithy:2\n")
+            .build();
+        assert!(matches!(res, Err(DeckerError::Parse { line: 1, .. })));
+    }
+
+    #[test]
+    fn test_no_record() {
+        let fname = std::env::temp_dir().join("decker_test_no_record_history");
+        std::fs::write(&fname, "100\tSmithy\n").unwrap();
+        let fname = fname.to_str().unwrap();
+        let conf = ConfigBuilder::new()
+            .card_data(CARDS)
+            .history_file(fname)
+            .record(false)
+            .build()
+            .unwrap();
+        // the header has to say so or rerunning it would record the game
+        assert!(conf
+            .options
+            .contains(&format!("--history={} --no-record", fname)));
+        assert!(conf.history_file.is_none());
+    }
+
+    #[test]
+    fn test_per_group() {
+        let conf = ConfigBuilder::new()
+            .card_data(CARDS)
+            .config_data("per-group = Core:2\n")
+            .build()
+            .unwrap();
+        assert_eq!(conf.per_group.get("Core"), Some(&2));
+        let res = ConfigBuilder::new()
+            .card_data(CARDS)
+            .per_group("Seaside", 2)
+            .build();
+        assert!(matches!(res, Err(DeckerError::Config(_))));
+        let res = ConfigBuilder::new()
+            .card_data(CARDS)
+            .per_group("Guilds", 1)
+            .build();
+        assert_eq!(
+            res.err(),
+            Some(DeckerError::UnknownGroup("Guilds".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_constraint() {
+        let conf = ConfigBuilder::new()
+            .card_data(CARDS)
+            .config_data("constraint = count(kw:+action & !type:Duration) >= 1\n")
+            .build()
+            .unwrap();
+        assert_eq!(conf.expressions[0].min, 1);
+        let res = ConfigBuilder::new()
+            .card_data(CARDS)
+            .config_data("# nothing\n  constraint = count(kw:+action) => 1\n")
+            .build();
+        assert!(matches!(
+            res,
+            Err(DeckerError::Parse {
+                line: 2,
+                column: Some(35),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_pairs() {
+        let conf = ConfigBuilder::new()
+            .card_data(CARDS)
+            .forbid_together(&["Village+Smithy"])
+            .config_data("require-together = Smithy+Lighthouse\n")
+            .build()
+            .unwrap();
+        assert_eq!(
+            conf.forbidden_pairs,
+            vec![("Village".to_string(), "Smithy".to_string())]
+        );
+        assert_eq!(
+            conf.required_pairs,
+            vec![("Smithy".to_string(), "Lighthouse".to_string())]
+        );
+        let res = ConfigBuilder::new()
+            .card_data(CARDS)
+            .forbid_together(&["Village+Rats"])
+            .build();
+        assert!(matches!(res, Err(DeckerError::Config(_))));
+    }
+
+    #[test]
+    fn test_filters() {
+        let conf = ConfigBuilder::new()
+            .card_data(CARDS)
+            .exclude_types(&["Duration"])
+            .exclude_keywords(&["+action"])
+            .include(&["Village"])
+            .build()
+            .unwrap();
+        let names: Vec<&str> = conf.piles.iter().map(|p| p.get_name()).collect();
+        assert_eq!(names, vec!["Copper", "Smithy", "Village"]);
+        assert_eq!(
+            conf.filter_counts,
+            vec![
+                ("--exclude-type=Duration".to_string(), 1),
+                ("--exclude-keyword=+action".to_string(), 0)
+            ]
+        );
+        let res = ConfigBuilder::new()
+            .card_data(CARDS)
+            .exclude_costs(&["coin"])
+            .build();
+        assert!(matches!(res, Err(DeckerError::Config(_))));
+    }
+
+    #[test]
+    fn test_veto() {
+        let conf = ConfigBuilder::new()
+            .card_data(CARDS)
+            .complete(&["Village", "Smithy"])
+            .veto(&["Smithy"])
+            .build()
+            .unwrap();
+        assert!(conf.complete.is_none());
+        assert_eq!(
+            conf.load_code.unwrap().piles,
+            vec![("Village".to_string(), false)]
+        );
+        assert!(!conf.piles.iter().any(|p| p.get_name() == "Smithy"));
+        let res = ConfigBuilder::new()
+            .card_data(CARDS)
+            .complete(&["Village", "Smithy"])
+            .veto(&["Lighthouse"])
+            .build();
+        assert!(matches!(res, Err(DeckerError::Config(_))));
+    }
+
+    #[test]
+    fn test_disjoint() {
+        let mut conf = ConfigBuilder::new()
+            .card_file("../dat/cards.dat")
+            .seed(3)
+            .count(4)
+            .disjoint(true)
+            .build()
+            .unwrap();
+        let col = CardCollectionPtr::new_state(&conf.piles);
+        let sels = conf.make_selections(col).unwrap();
+        assert_eq!(sels.len(), 4);
+        let mut seen = BTreeSet::new();
+        for (sel, problems) in &sels {
+            assert!(problems.is_empty());
+            for p in sel.get_piles() {
+                if p.get_supply() && p.get_kingdom() {
+                    assert!(seen.insert(p.get_name().to_string()), "{}", p.get_name());
+                }
+            }
         }
-        // now shuffle the prefixes
-        let mut shuffle_prefixes = Vec::from_iter(group_prefixes);
-        let n_sets = shuffle_prefixes.len();
-        // wow, could have named these better
-        for _ in 0..3 {
-            for i in (1..n_sets).rev() {
-                let pos = rand.gen::<usize>() % i;
-                shuffle_prefixes.swap(i, pos);
+        assert!(seen.len() >= 40);
+        // not enough kingdom piles for that many
+        let res = ConfigBuilder::new()
+            .card_file("../dat/cards.dat")
+            .count(100)
+            .disjoint(true)
+            .build();
+        match res {
+            Err(DeckerError::Config(msg)) => {
+                assert!(msg.starts_with("100 disjoint selections need at least 1000"))
             }
+            _ => panic!("expected a config error"),
+        }
+        // running out part way is reported as such
+        let run = |b: ConfigBuilder| {
+            let mut conf = b
+                .card_file("../dat/cards.dat")
+                .seed(1)
+                .disjoint(true)
+                .build()
+                .unwrap();
+            let col = CardCollectionPtr::new_state(&conf.piles);
+            conf.make_selections(col).err()
+        };
+        assert_eq!(
+            run(ConfigBuilder::new().count(20).min_type("Reaction", 4)),
+            Some(DeckerError::SearchExhausted(
+                "Ran out of kingdom piles after 4 of 20 disjoint selections".to_string()
+            ))
+        );
+        // but other errors come through as they are
+        assert_eq!(
+            run(ConfigBuilder::new().count(15).min_keyword("+buy", 5)),
+            Some(DeckerError::Unsatisfiable(
+                "No matches found for keyword +buy".to_string()
+            ))
+        );
+        // fine if they can share
+        assert!(ConfigBuilder::new()
+            .card_file("../dat/cards.dat")
+            .count(100)
+            .build()
+            .is_ok());
+    }
+
+    // each HashMap has its own order, so a few builds would pick
+    // different kingdoms if the type constraints followed it
+    #[test]
+    fn test_types_order() {
+        let names = || {
+            let mut conf = ConfigBuilder::new()
+                .card_file("../dat/cards.dat")
+                .seed(3)
+                .min_type("Treasure", 2)
+                .min_type("Attack", 2)
+                .min_type("Duration", 2)
+                .min_type("Reaction", 1)
+                .build()
+                .unwrap();
+            let col = CardCollectionPtr::new_state(&conf.piles);
+            let (sel, _) = conf.make_selection(col).unwrap();
+            sel.get_piles()
+                .iter()
+                .map(|p| p.get_name().to_string())
+                .collect::<Vec<_>>()
+        };
+        let first = names();
+        for _ in 0..5 {
+            assert_eq!(names(), first);
+        }
+    }
+
+    #[test]
+    fn test_keywords() {
+        for seed in 1..=10 {
+            let mut conf = ConfigBuilder::new()
+                .card_file("../dat/cards.dat")
+                .seed(seed)
+                .min_keyword("+buy", 3)
+                .max_keyword("+card", 1)
+                .build()
+                .unwrap();
+            let col = CardCollectionPtr::new_state(&conf.piles);
+            let (sel, _) = conf.make_selection(col).unwrap();
+            let with = |kw: &str| {
+                sel.get_piles()
+                    .iter()
+                    .filter(|p| p.get_keywords().contains(kw))
+                    .count()
+            };
+            assert!(with("+buy") >= 3, "seed {}", seed);
+            assert!(with("+card") <= 1, "seed {}", seed);
         }
-        let mut i = 0;
-        while i < n_sets && chosen_prefixes.len() < (suggested_max as usize) {
-            chosen_prefixes.insert(shuffle_prefixes[i].to_string());
-            i += 1;
+        // there's nothing to add for an unknown keyword
+        let mut conf = ConfigBuilder::new()
+            .card_file("../dat/cards.dat")
+            .min_keyword("+bye", 1)
+            .build()
+            .unwrap();
+        let col = CardCollectionPtr::new_state(&conf.piles);
+        assert_eq!(
+            conf.make_selection(col).err(),
+            Some(DeckerError::Unsatisfiable(
+                "No matches found for keyword +bye".to_string()
+            ))
+        );
+        // but at most none of them is fine
+        let mut conf = ConfigBuilder::new()
+            .card_file("../dat/cards.dat")
+            .max_keyword("+bye", 0)
+            .build()
+            .unwrap();
+        let col = CardCollectionPtr::new_state(&conf.piles);
+        assert!(conf.make_selection(col).is_ok());
+    }
+
+    // kingdom piles from each prefix
+    fn prefix_counts(quotas: &[(&str, u8)], seed: u64) -> BTreeMap<String, u8> {
+        let mut b = ConfigBuilder::new()
+            .card_file("../dat/cards.dat")
+            .seed(seed);
+        for (prefix, count) in quotas {
+            b = b.per_group(prefix, *count);
         }
-        let mut new_files = PileSet::new();
-        for p2 in p_set {
-            if chosen_prefixes.contains(&group_name_prefix(p2.get_card_group())) {
-                new_files.insert(p2.clone());
+        let mut conf = b.build().unwrap();
+        let col = CardCollectionPtr::new_state(&conf.piles);
+        let (sel, _) = conf.make_selection(col).unwrap();
+        let mut res = BTreeMap::new();
+        for p in sel.get_piles() {
+            if p.get_supply() && p.get_kingdom() {
+                *res.entry(group_name_prefix(p.get_card_group()))
+                    .or_default() += 1;
             }
         }
-        p_set = new_files;
+        res
     }
 
-    // Now let's work out how many optional extras we need
-    let opt_extra = cli.landscape_count.unwrap_or_else(|| {
-        let x = (rand.gen::<u64>() % 7) as u8;
-        if x < 3 {
-            x
-        } else {
-            0
-        }
-    });
-
-    let why = cli.why;
-    let more_info = cli.info;
-    let disable_anti_cursors = cli.no_anti_cursor;
-    let disable_attack_react = cli.no_attack_react;
-
-    // println!("Optional Extra: {opt_extra:?}");
-    // println!("Validate: {validate:?}");
-    // println!("List Collection: {list_collection:?}");
-    // println!("Disable anti cursors: {disable_anti_cursors:?}");
-    // println!("Disable attack react: {disable_attack_react:?}");
-    // println!("Max cost repeat: {max_cost_repeat:?}");
-    // println!("Min types: {min_types:?}");
-    // println!("Max types: {max_types:?}");
-    // println!("Piles: {p_set:?}");
-    // println!("Includes: {include_piles:?}");
-
-    Ok(Config {
-        rand: Box::new(rand),
-        why,
-        more_info,
-        optional_extras: opt_extra,
-        validate,
-        list_collection,
-        disable_anti_cursors,
-        disable_attack_react,
-        max_cost_repeat,
-        min_types,
-        max_types,
-        piles: p_set,
-        includes: include_piles,
-    })
+    #[test]
+    fn test_per_group_extra_piles() {
+        let build = |quotas: &[(&str, u8)], seed: u64| {
+            let mut b = ConfigBuilder::new()
+                .card_file("../dat/cards.dat")
+                .seed(seed);
+            for (prefix, count) in quotas {
+                b = b.per_group(prefix, *count);
+            }
+            b.build().unwrap()
+        };
+        let full = [("Prosperity", 6), ("Cornucopia", 4)];
+        assert!(build(&full, 1).warnings.contains(
+            &"--per-group fills the kingdom so Way of the Mouse, Young Witch can't be picked (no room for an extra pile).".to_string()
+        ));
+        for seed in 1..=10 {
+            let mut conf = build(&full, seed);
+            let col = CardCollectionPtr::new_state(&conf.piles);
+            let (sel, _) = conf.make_selection(col).unwrap();
+            assert!(!sel.get_piles().iter().any(needs_extra_pile));
+        }
+        // with room to spare they can be picked as usual
+        assert!(!build(&[("Cornucopia", 4)], 1)
+            .warnings
+            .iter()
+            .any(|w| w.starts_with("--per-group")));
+        let res = ConfigBuilder::new()
+            .card_file("../dat/cards.dat")
+            .per_group("Prosperity", 6)
+            .per_group("Cornucopia", 4)
+            .include(&["Young Witch"])
+            .build();
+        match res {
+            Err(DeckerError::Config(msg)) => assert_eq!(
+                msg,
+                "--per-group fills the kingdom so there is no room for the extra pile Young Witch needs."
+            ),
+            _ => panic!("expected a config error"),
+        }
+    }
+
+    #[test]
+    fn test_per_group_fills_kingdom() {
+        let counts = prefix_counts(&[("Seaside", 5), ("Guilds", 5)], 1);
+        assert_eq!(
+            counts,
+            BTreeMap::from([("Guilds".to_string(), 5), ("Seaside".to_string(), 5)])
+        );
+        let counts = prefix_counts(&[("Seaside", 10)], 1);
+        assert_eq!(counts, BTreeMap::from([("Seaside".to_string(), 10)]));
+        // Young Witch would need an 11th pile so it stays out (see below)
+        for seed in 1..=10 {
+            let counts = prefix_counts(&[("Prosperity", 6), ("Cornucopia", 4)], seed);
+            assert_eq!(counts["Prosperity"], 6);
+            assert_eq!(counts["Cornucopia"], 4);
+        }
+        // no room for anything else
+        let res = ConfigBuilder::new()
+            .card_file("../dat/cards.dat")
+            .per_group("Seaside", 5)
+            .per_group("Guilds", 5)
+            .include(&["Village"])
+            .build();
+        assert!(matches!(res, Err(DeckerError::Config(_))));
+        let res = ConfigBuilder::new()
+            .card_file("../dat/cards.dat")
+            .per_group("Seaside", 5)
+            .per_group("Guilds", 5)
+            .min_type("Reaction", 1)
+            .build();
+        match res {
+            Err(DeckerError::Config(msg)) => assert_eq!(
+                msg,
+                "--per-group fills the kingdom but its groups only have 0 piles for --min-type=Reaction:1."
+            ),
+            _ => panic!("expected a config error"),
+        }
+        let res = ConfigBuilder::new()
+            .card_file("../dat/cards.dat")
+            .per_group("Seaside", 2)
+            .include(&["Lighthouse", "Island", "Lookout"])
+            .build();
+        assert!(matches!(res, Err(DeckerError::Config(_))));
+    }
 }
@@ -1,40 +1,87 @@
+use std::collections::BTreeSet;
 use std::rc::Rc;
 
 use crate::actions::{AddGroup, ConstraintActionPtr, FindBane, FindPile};
 use crate::collections::CardCollectionPtr;
 use crate::costs::{Cost, CostSet};
+use crate::errors::{DeckerError, DeckerResult};
+use crate::interactions::Interaction;
+use crate::piles::PilePtr;
 use crate::properties::PropertyPtr;
 use crate::selections::SelectionPtr;
 use crate::MANY;
 
 use crate::properties::{
-    CardGroupProperty, CostAndTypeProperty, FailProperty, KeywordProperty, NameProperty,
-    NoteProperty, OtherInteractionProperty, TypeProperty,
+    AllProperty, CardGroupProperty, CostAndTypeProperty, CostProperty, KeywordProperty,
+    KingdomAndSupplyProperty, NameProperty, NoteProperty, OtherInteractionProperty, TypeProperty,
 };
 
-pub fn bane_constraint(col: &CardCollectionPtr) -> ConstraintPtr {
-    let has_yw = NameProperty::make_ptr(&"Young Witch".to_string());
-    let mut cs = CostSet::new();
-    cs.insert(Cost::new_s(2));
-    cs.insert(Cost::new_s(3));
-    let bane_cost = CostAndTypeProperty::make_ptr_set("Action".to_string(), cs);
-    let begin = match col.get_iterators(&bane_cost) {
+// Cards with extra(name.low.high) need an additional kingdom pile
+// costing low..=high, and with extra(name.low.high.Type) it must also be
+// a Type (eg Young Witch's extra(bane.2.3.Action)).
+// One constraint for each such pile in the collection.
+pub fn extra_pile_constraints(col: &CardCollectionPtr) -> Vec<ConstraintPtr> {
+    let mut cons = vec![];
+    // sorted by name so the order doesn't depend on the shuffle
+    let piles: BTreeSet<PilePtr> = col.get_piles().iter().cloned().collect();
+    for p in &piles {
+        for it in p.get_other_interactions() {
+            if let Interaction::Extra {
+                name,
+                low,
+                high,
+                pile_type,
+            } = it
+            {
+                let cs = CostSet::from_iter((*low..=*high).map(Cost::new_s));
+                let extra_prop = match pile_type {
+                    Some(t) => CostAndTypeProperty::make_ptr_set(t.to_string(), cs),
+                    None => AllProperty::make_ptr(&[
+                        CostProperty::make_ptr_set(cs, true),
+                        KingdomAndSupplyProperty::make_ptr(),
+                    ]),
+                };
+                cons.push(extra_pile_constraint(col, p.get_name(), name, &extra_prop));
+            }
+        }
+    }
+    cons
+}
+
+fn extra_pile_constraint(
+    col: &CardCollectionPtr,
+    pile_name: &str,
+    extra_name: &str,
+    extra_prop: &PropertyPtr,
+) -> ConstraintPtr {
+    let has_card = NameProperty::make_ptr(&pile_name.to_string());
+    let begin = match col.get_iterators(extra_prop) {
+        // nothing could ever be the extra pile so the card itself can't be used
         None => {
-            return Constraint::unsatisfiable(&"Failed bane constraint".to_string());
+            return Constraint::make_ptr_full(
+                format!("Failed {} constraint", extra_name),
+                Some(has_card.clone()),
+                &has_card,
+                None,
+                1,
+                MANY,
+                MANY,
+                1,
+            );
         }
         Some(v) => v,
     };
-    let fix = FindBane::make_ptr(col, &begin);
-    let has_bane = NoteProperty::make_ptr(&"hasBane".to_string());
-    // if we have less than 1 YoungWitch do nothing
-    // if we have less than 1 hasBane note actionRequired   (only ever have 1 note)
+    let fix = FindBane::make_ptr(col, &begin, extra_name);
+    let has_extra = NoteProperty::make_ptr(&extra_note(extra_name));
+    // if we have less than 1 of the card do nothing
+    // if we have less than 1 has... note actionRequired   (only ever have 1 note)
     //.can accept more is empty (1,1)
-    // from 1 .. MANY hasBane go inactive
+    // from 1 .. MANY has... go inactive
     // more than MANY -> fail
     Constraint::make_ptr_full(
-        "bane".to_string(),
-        Some(has_yw),
-        &has_bane,
+        extra_name.to_string(),
+        Some(has_card),
+        &has_extra,
         Some(fix),
         1,
         1,
@@ -43,6 +90,20 @@ pub fn bane_constraint(col: &CardCollectionPtr) -> ConstraintPtr {
     )
 }
 
+// eg bane -> Bane
+pub(crate) fn extra_tag(extra_name: &str) -> String {
+    let mut c = extra_name.chars();
+    match c.next() {
+        Some(f) => f.to_uppercase().chain(c).collect(),
+        None => String::new(),
+    }
+}
+
+// eg bane -> hasBane
+pub(crate) fn extra_note(extra_name: &str) -> String {
+    format!("has{}", extra_tag(extra_name))
+}
+
 pub fn prosp_constraint(col: &CardCollectionPtr) -> ConstraintPtr {
     let group_pros = CardGroupProperty::make_ptr("Prosperity");
     let has_pros_base = NoteProperty::make_ptr(&"addedProsperity-base".to_string());
@@ -64,10 +125,7 @@ pub fn prosp_constraint(col: &CardCollectionPtr) -> ConstraintPtr {
 pub fn curser_constraint(col: &CardCollectionPtr, threshold: u64) -> Option<ConstraintPtr> {
     let curser = KeywordProperty::make_ptr("curser", false);
     let trash = KeywordProperty::make_ptr("trash_any", true);
-    let begin = match col.get_iterators(&trash) {
-        Some(v) => v,
-        None => return None,
-    };
+    let begin = col.get_iterators(&trash)?;
     let fix = FindPile::make_ptr(col, &begin);
     Some(Constraint::make_ptr_full(
         "counterCurser".to_string(),
@@ -84,11 +142,8 @@ pub fn curser_constraint(col: &CardCollectionPtr, threshold: u64) -> Option<Cons
 pub fn attack_react_constraint(col: &CardCollectionPtr, threshold: u64) -> Option<ConstraintPtr> {
     let attack = TypeProperty::make_ptr("Attack", true);
     // only want kingdom and supply piles
-    let react = OtherInteractionProperty::make_ptr("react(Attack)", true);
-    let begin = match col.get_iterators(&react) {
-        Some(v) => v,
-        None => return None,
-    };
+    let react = OtherInteractionProperty::make_ptr(Interaction::React("Attack".to_string()), true);
+    let begin = col.get_iterators(&react)?;
     let fix = FindPile::make_ptr(col, &begin);
     Some(Constraint::make_ptr_full(
         "counterAttack".to_string(),
@@ -122,14 +177,21 @@ pub struct Constraint {
 }
 
 impl Constraint {
-    pub(crate) fn act(&self, start: &SelectionPtr) -> Result<SelectionPtr, String> {
+    pub fn act(&self, start: &SelectionPtr) -> DeckerResult<SelectionPtr> {
         match &self.action {
             Some(act) => act.apply(&self.why, start),
-            None => Err("".to_string()),
+            None => Err(DeckerError::Unsatisfiable(format!(
+                "{} needs action but has none",
+                self.why
+            ))),
         }
     }
 
-    pub(crate) fn get_status(&self, sel: &SelectionPtr) -> ConsResult {
+    pub fn get_label(&self) -> &str {
+        &self.why
+    }
+
+    pub fn get_status(&self, sel: &SelectionPtr) -> ConsResult {
         let piles = &sel.get_piles();
         if let Some(prec) = &self.precondition {
             let mut count = 0;
@@ -172,7 +234,7 @@ impl Constraint {
         ConsResult::ConsActionReq
     }
 
-    pub(crate) fn make_ptr(
+    pub fn make_ptr(
         label: String,
         prop: &PropertyPtr,
         act: Option<ConstraintActionPtr>,
@@ -191,7 +253,8 @@ impl Constraint {
         })
     }
 
-    pub(crate) fn make_ptr_full(
+    #[allow(clippy::too_many_arguments)]
+    pub fn make_ptr_full(
         label: String,
         pre: Option<PropertyPtr>,
         prop: &PropertyPtr,
@@ -212,19 +275,49 @@ impl Constraint {
             why: label,
         })
     }
-
-    fn unsatisfiable(label: &String) -> ConstraintPtr {
-        Rc::new(Constraint {
-            property: FailProperty::make_ptr(),
-            precondition: None,
-            action: None,
-            prop_active: 0,
-            prop_satisfied: 0,
-            prop_inactive: 0,
-            prop_broken: 0,
-            why: label.to_string(),
-        })
-    }
 }
 
 pub type ConstraintPtr = Rc<Constraint>;
+
+#[cfg(test)]
+mod test_constraints {
+    use crate::collections::CardCollectionPtr;
+    use crate::costs::Cost;
+    use crate::ConfigBuilder;
+
+    // the pile tagged tag in a selection including pile_name
+    fn extra_pile(pile_name: &str, tag: &str, seed: u64) -> crate::PilePtr {
+        let mut conf = ConfigBuilder::new()
+            .card_file("../dat/cards.dat")
+            .seed(seed)
+            .include(&[pile_name])
+            .build()
+            .unwrap();
+        let col = CardCollectionPtr::new_state(&conf.piles);
+        let (sel, _) = conf.make_selection(col).unwrap();
+        let extras: Vec<_> = sel
+            .get_piles()
+            .iter()
+            .filter(|p| sel.get_tags(p).contains(&tag.to_string()))
+            .cloned()
+            .collect();
+        assert_eq!(extras.len(), 1, "seed {}", seed);
+        assert_eq!(sel.get_normal_pile_count(), 11);
+        extras[0].clone()
+    }
+
+    #[test]
+    fn test_extra_piles() {
+        let two_three = [Cost::new_s(2), Cost::new_s(3)];
+        for seed in 1..=20 {
+            // both need an Action
+            let bane = extra_pile("Young Witch", "Bane", seed);
+            assert!(bane.get_kingdom() && bane.get_supply());
+            assert!(bane.get_types().contains("Action"));
+            assert!(bane.get_costs().iter().any(|c| two_three.contains(c)));
+            let mouse = extra_pile("Way of the Mouse", "Mouse", seed);
+            assert!(mouse.get_types().contains("Action"));
+            assert!(mouse.get_costs().iter().any(|c| two_three.contains(c)));
+        }
+    }
+}
@@ -1,15 +1,16 @@
 use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
+use crate::piles::PilePtr;
 use crate::{short_value, MAXCOINCOST};
 
 // Could have possibly used a tuple struct but
 // I don't want people to need to know meaning of indices
 // Could possibly have made each of these Option<>
 //  and then make callers check if they exist
-#[derive(Hash, Eq, PartialEq, Copy, Clone, Debug, Deserialize)]
+#[derive(Hash, Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Debug, Deserialize, Serialize)]
 pub struct Cost {
     coin: Option<i8>,
     potion: Option<i8>,
@@ -50,6 +51,26 @@ impl Cost {
         )
     }
 
+    // For people rather than debugging eg $4, $2 P, 8D
+    pub fn label(&self) -> String {
+        let mut parts = vec![];
+        if let Some(c) = self.coin {
+            parts.push(format!("${}", c));
+        }
+        match self.potion {
+            Some(1) => parts.push("P".to_string()),
+            Some(p) => parts.push(format!("{}P", p)),
+            None => {}
+        }
+        if let Some(d) = self.debt {
+            parts.push(format!("{}D", d));
+        }
+        if parts.is_empty() {
+            return "$0".to_string();
+        }
+        parts.join(" ")
+    }
+
     pub(crate) fn has_debt(&self) -> bool {
         self.debt.is_some()
     }
@@ -87,6 +108,59 @@ impl Cost {
     }
 }
 
+// Range of coin costs for --min-cost/--max-cost, eg 2..3 or 5..
+// Potion and debt don't matter ($2 P is in 2..3) but piles which only
+// cost debt aren't in any band.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CostBand {
+    pub low: i8,
+    pub high: Option<i8>,
+}
+
+impl CostBand {
+    // "4", "2..3" or "5.."
+    pub fn parse(s: &str) -> Option<CostBand> {
+        let (low, high) = match s.split_once("..") {
+            Some((low, "")) => (low.trim().parse().ok()?, None),
+            Some((low, high)) => (low.trim().parse().ok()?, Some(high.trim().parse().ok()?)),
+            None => {
+                let c = s.trim().parse().ok()?;
+                (c, Some(c))
+            }
+        };
+        match high {
+            Some(h) if h < low => None,
+            _ if low < 0 => None,
+            _ => Some(CostBand { low, high }),
+        }
+    }
+
+    pub fn contains(&self, c: &Cost) -> bool {
+        match c.get_coin() {
+            Some(coin) => coin >= self.low && self.high.is_none_or(|h| coin <= h),
+            None => false,
+        }
+    }
+
+    // Every cost in the band from these piles' costs
+    pub fn costs_from(&self, piles: &[PilePtr]) -> CostSet {
+        piles
+            .iter()
+            .flat_map(|p| p.get_costs().iter())
+            .filter(|c| self.contains(c))
+            .copied()
+            .collect()
+    }
+
+    pub fn label(&self) -> String {
+        match self.high {
+            None => format!("${}+", self.low),
+            Some(h) if h == self.low => format!("${}", h),
+            Some(h) => format!("${}-${}", self.low, h),
+        }
+    }
+}
+
 // This is a bit tricky
 // What I had in c++ was operator==(CostTarget)
 //   which then examined type
@@ -130,7 +204,7 @@ pub trait CostTarget: std::fmt::Debug {
 //
 //    The question is, do I actually need to do that?
 //    Can I create the set and move it into the target?
-//type TargetSet = BTreeSet<Box<dyn CostTarget>>;
+//type TargetSet = BTreeSet<Rc<dyn CostTarget>>;
 
 // Attaching the necessary traits for Tree or HashSet to the trait turns out to be
 // _difficult_.      I've decided to just make a vector and do duplicate removal
@@ -339,7 +413,7 @@ impl CostTarget for CostInSet {
     fn add_votes(&self, current_costs: &CostSet, votes: &mut CostVotes) -> bool {
         let mut matched_count = 0;
         for c in current_costs {
-            if self.costs.get(c).is_some() {
+            if self.costs.contains(c) {
                 matched_count += 1
             }
         }
@@ -418,12 +492,12 @@ impl CostVotes {
 // >= (+/-), relative (limit..)
 // =, absolute iter::once(limit)
 enum Comparison {
-    LessThan(i8),
-    GreaterThan(i8),
-    EqualTo(i8),
+    LessThan,
+    GreaterThan,
+    EqualTo,
 }
 
-pub fn decode_cost(s: &str) -> Option<Box<dyn CostTarget>> {
+pub fn decode_cost(s: &str) -> Option<Rc<dyn CostTarget>> {
     let matches_required = 6;
     let unmet_weight = 3;
     let met_weight = 1;
@@ -444,7 +518,7 @@ pub fn decode_cost(s: &str) -> Option<Box<dyn CostTarget>> {
         let upper: i8 = upper.parse::<u8>().ok()? as i8;
         let cs = CostSet::from_iter((lower..=upper).map(Cost::new_s));
         // Create final struct
-        return Some(Box::new(CostInSet::new(
+        return Some(Rc::new(CostInSet::new(
             upto_matches,
             unmet_weight,
             met_weight,
@@ -461,18 +535,18 @@ pub fn decode_cost(s: &str) -> Option<Box<dyn CostTarget>> {
     }
 
     let comp = match comp {
-        "<" => Comparison::LessThan(val),
-        ">" => Comparison::GreaterThan(val),
-        "" => Comparison::EqualTo(val),
+        "<" => Comparison::LessThan,
+        ">" => Comparison::GreaterThan,
+        "" => Comparison::EqualTo,
         _ => return None,
     };
 
     let coin_cost = 10;
     let limit = if relative { coin_cost + val } else { val };
     let (upper, lower) = match comp {
-        Comparison::LessThan(_) => (0, limit),
-        Comparison::GreaterThan(_) => (limit, MAXCOINCOST),
-        Comparison::EqualTo(_) => (limit, limit),
+        Comparison::LessThan => (0, limit),
+        Comparison::GreaterThan => (limit, MAXCOINCOST),
+        Comparison::EqualTo => (limit, limit),
     };
 
     for val in lower..=upper {
@@ -484,7 +558,7 @@ pub fn decode_cost(s: &str) -> Option<Box<dyn CostTarget>> {
         if value <= 0 || value > cost_bound {
             return None;
         }
-        return Some(Box::new(CostRelative::new(
+        return Some(Rc::new(CostRelative::new(
             matches_required,
             unmet_weight,
             met_weight,
@@ -497,7 +571,7 @@ pub fn decode_cost(s: &str) -> Option<Box<dyn CostTarget>> {
         if value <= 0 || value > cost_bound {
             return None;
         }
-        return Some(Box::new(CostRelative::new(
+        return Some(Rc::new(CostRelative::new(
             matches_required,
             unmet_weight,
             met_weight,
@@ -509,7 +583,7 @@ pub fn decode_cost(s: &str) -> Option<Box<dyn CostTarget>> {
         if value <= 0 || value > cost_bound {
             return None;
         }
-        return Some(Box::new(CostUpto::new(
+        return Some(Rc::new(CostUpto::new(
             upto_matches,
             unmet_weight,
             met_weight,
@@ -520,7 +594,7 @@ pub fn decode_cost(s: &str) -> Option<Box<dyn CostTarget>> {
         if value <= 0 || value > cost_bound {
             return None;
         }
-        return Some(Box::new(CostRelative::new(
+        return Some(Rc::new(CostRelative::new(
             matches_required,
             unmet_weight,
             met_weight,
@@ -532,7 +606,7 @@ pub fn decode_cost(s: &str) -> Option<Box<dyn CostTarget>> {
         if value <= 0 || value > cost_bound {
             return None;
         }
-        return Some(Box::new(CostRelative::new(
+        return Some(Rc::new(CostRelative::new(
             matches_required,
             unmet_weight,
             met_weight,
@@ -548,7 +622,7 @@ pub fn decode_cost(s: &str) -> Option<Box<dyn CostTarget>> {
         for v in value..=MAXCOINCOST {
             cs.insert(Cost::new_s(v));
         }
-        return Some(Box::new(CostInSet::new(
+        return Some(Rc::new(CostInSet::new(
             upto_matches,
             unmet_weight,
             met_weight,
@@ -557,3 +631,48 @@ pub fn decode_cost(s: &str) -> Option<Box<dyn CostTarget>> {
     }
     None
 }
+
+#[cfg(test)]
+mod test_costs {
+    use super::*;
+    use crate::{CardCollectionPtr, ConfigBuilder};
+
+    #[test]
+    fn test_cost_band() {
+        let band = CostBand::parse("2..3").unwrap();
+        assert!(band.contains(&Cost::new_s(2)));
+        assert!(band.contains(&Cost::new(Some(3), Some(1), None)));
+        assert!(!band.contains(&Cost::new_s(4)));
+        assert!(!band.contains(&Cost::new(None, None, Some(8))));
+        assert_eq!(band.label(), "$2-$3");
+        assert_eq!(CostBand::parse("5..").unwrap().label(), "$5+");
+        assert_eq!(CostBand::parse("4").unwrap().label(), "$4");
+        assert_eq!(CostBand::parse("3..2"), None);
+        assert_eq!(CostBand::parse("x.."), None);
+    }
+
+    #[test]
+    fn test_cost_band_selection() {
+        for seed in 1..=10 {
+            let mut conf = ConfigBuilder::new()
+                .card_file("../dat/cards.dat")
+                .seed(seed)
+                .min_costs(&["2..3:4"])
+                .max_costs(&["5..:1"])
+                .build()
+                .unwrap();
+            let col = CardCollectionPtr::new_state(&conf.piles);
+            let (sel, _) = conf.make_selection(col).unwrap();
+            let costing = |band: &str| {
+                let band = CostBand::parse(band).unwrap();
+                sel.get_piles()
+                    .iter()
+                    .filter(|p| p.get_kingdom() && p.get_supply())
+                    .filter(|p| p.get_costs().iter().any(|c| band.contains(c)))
+                    .count()
+            };
+            assert!(costing("2..3") >= 4, "seed {}", seed);
+            assert!(costing("5..") <= 1, "seed {}", seed);
+        }
+    }
+}
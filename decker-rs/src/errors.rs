@@ -0,0 +1,126 @@
+use std::fmt;
+
+// Everything which can go wrong between reading the data files and
+// producing a selection.
+// Each variant maps to its own process exit code (see exit_code()) so
+// scripts can tell failures apart:
+//   1  bad combination of options
+//   2  search exhausted (no selection could be built)
+//   3  collection failed validation
+//   4  a constraint can not be satisfied
+//   5  could not read a file
+//   6  could not parse a data file
+//   7  unknown card
+//   8  unknown group
+//   9  unknown box
+//   10 lint found problems in the card file
+//   11 kingdom code can't be used with this card database
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeckerError {
+    Config(String),
+    FileIO {
+        filename: String,
+        reason: String,
+    },
+    Parse {
+        source: String,
+        line: usize,
+        column: Option<usize>,
+        message: String,
+    },
+    UnknownCard(String),
+    UnknownGroup(String),
+    UnknownBox {
+        name: String,
+        source: String,
+    },
+    Validation(Vec<String>),
+    Unsatisfiable(String),
+    SearchExhausted(String),
+    // number of problems reported by lint
+    Lint(usize),
+    BadCode(String),
+    // More than one problem found in the same pass (eg several bad lines)
+    Multiple(Vec<DeckerError>),
+}
+
+pub type DeckerResult<T> = Result<T, DeckerError>;
+
+impl DeckerError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            DeckerError::Config(_) => 1,
+            DeckerError::SearchExhausted(_) => 2,
+            DeckerError::Validation(_) => 3,
+            DeckerError::Unsatisfiable(_) => 4,
+            DeckerError::FileIO { .. } => 5,
+            DeckerError::Parse { .. } => 6,
+            DeckerError::UnknownCard(_) => 7,
+            DeckerError::UnknownGroup(_) => 8,
+            DeckerError::UnknownBox { .. } => 9,
+            DeckerError::Lint(_) => 10,
+            DeckerError::BadCode(_) => 11,
+            DeckerError::Multiple(v) => v.first().map_or(1, |e| e.exit_code()),
+        }
+    }
+
+    // Wrap up a list of errors, avoiding Multiple for a single item
+    pub fn from_list(mut errors: Vec<DeckerError>) -> Option<DeckerError> {
+        match errors.len() {
+            0 => None,
+            1 => errors.pop(),
+            _ => Some(DeckerError::Multiple(errors)),
+        }
+    }
+}
+
+impl fmt::Display for DeckerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeckerError::Config(s) => write!(f, "{}", s),
+            DeckerError::FileIO { filename, reason } => {
+                write!(f, "Can't open file {}: {}", filename, reason)
+            }
+            DeckerError::Parse {
+                source,
+                line,
+                column,
+                message,
+            } => match column {
+                Some(c) => write!(f, "{}:{}:{}: {}", source, line, c, message),
+                None => write!(f, "{}:{}: {}", source, line, message),
+            },
+            DeckerError::UnknownCard(s) => write!(f, "Unknown card {}", s),
+            DeckerError::UnknownGroup(s) => write!(f, "Unknown group {}", s),
+            DeckerError::UnknownBox { name, source } => {
+                write!(f, "Box {} not known in box file {}", name, source)
+            }
+            DeckerError::Validation(v) => {
+                write!(f, "Error validating collection:")?;
+                for s in v {
+                    write!(f, "\n{}", s)?;
+                }
+                Ok(())
+            }
+            DeckerError::Unsatisfiable(s) => write!(f, "Unsatisfiable constraint: {}", s),
+            DeckerError::SearchExhausted(s) => {
+                write!(f, "Error: empty selection\nPossible explanation: {}", s)
+            }
+            DeckerError::Lint(n) => write!(f, "{} problem(s) found in card file", n),
+            DeckerError::BadCode(s) => write!(f, "Can't use kingdom code: {}", s),
+            DeckerError::Multiple(v) => {
+                let mut first = true;
+                for e in v {
+                    if !first {
+                        writeln!(f)?;
+                    }
+                    first = false;
+                    write!(f, "{}", e)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for DeckerError {}
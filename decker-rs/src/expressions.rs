@@ -0,0 +1,212 @@
+use crate::properties::prelude::*;
+use crate::properties::PropertyPtr;
+use crate::MANY;
+
+// Constraints written as text, eg
+//   count(type:Action & kw:+action & !type:Attack) >= 2
+// Inside count(...):
+//   type:T  kw:K (or keyword:K)  group:G  name:P (a pile name)
+//   a & b   a | b   !a   (a)      with & binding tighter than |
+// The comparison is one of >=, >, <=, <, == (or =).
+// Every pile in the selection counts, but only kingdom piles get added
+// to reach a minimum (the same split as --min-type).
+#[derive(Clone)]
+pub struct ConstraintExpr {
+    pub text: String,
+    pub property: PropertyPtr,
+    pub min: u64,
+    pub max: u64,
+}
+
+// Errors are (column counted from 1, message)
+pub fn parse_constraint(text: &str) -> Result<ConstraintExpr, (usize, String)> {
+    let mut p = Parser {
+        chars: text.chars().collect(),
+        pos: 0,
+    };
+    p.expect("count")?;
+    p.expect("(")?;
+    let property = p.parse_or()?;
+    p.expect(")")?;
+    let op = match [">=", "<=", "==", "=", ">", "<"]
+        .into_iter()
+        .find(|op| p.eat(op))
+    {
+        Some(op) => op,
+        None => return p.error("expected one of >= > <= < =="),
+    };
+    p.skip_space();
+    let start = p.pos;
+    while p.pos < p.chars.len() && p.chars[p.pos].is_ascii_digit() {
+        p.pos += 1;
+    }
+    let n = match p.chars[start..p.pos]
+        .iter()
+        .collect::<String>()
+        .parse::<u64>()
+    {
+        Ok(n) if n < MANY => n,
+        _ => {
+            p.pos = start;
+            return p.error("expected a number");
+        }
+    };
+    p.skip_space();
+    if p.pos < p.chars.len() {
+        return p.error("unexpected text after the number");
+    }
+    let (min, max) = match op {
+        ">=" => (n, MANY),
+        ">" => (n + 1, MANY),
+        "<=" => (0, n),
+        "<" if n > 0 => (0, n - 1),
+        "<" => return Err((start + 1, "count can't be less than 0".to_string())),
+        _ => (n, n),
+    };
+    Ok(ConstraintExpr {
+        text: text.trim().to_string(),
+        property,
+        min,
+        max,
+    })
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn error<T>(&self, message: &str) -> Result<T, (usize, String)> {
+        Err((self.pos + 1, message.to_string()))
+    }
+
+    fn skip_space(&mut self) {
+        while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    // skip s (after any spaces) if it is next
+    fn eat(&mut self, s: &str) -> bool {
+        self.skip_space();
+        let len = s.chars().count();
+        if self.pos + len <= self.chars.len()
+            && self.chars[self.pos..self.pos + len]
+                .iter()
+                .copied()
+                .eq(s.chars())
+        {
+            self.pos += len;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, s: &str) -> Result<(), (usize, String)> {
+        match self.eat(s) {
+            true => Ok(()),
+            false => self.error(&format!("expected {}", s)),
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<PropertyPtr, (usize, String)> {
+        let mut props = vec![self.parse_and()?];
+        while self.eat("|") {
+            props.push(self.parse_and()?);
+        }
+        Ok(match props.len() {
+            1 => props.remove(0),
+            _ => AnyProperty::make_ptr(&props),
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<PropertyPtr, (usize, String)> {
+        let mut props = vec![self.parse_unary()?];
+        while self.eat("&") {
+            props.push(self.parse_unary()?);
+        }
+        Ok(match props.len() {
+            1 => props.remove(0),
+            _ => AllProperty::make_ptr(&props),
+        })
+    }
+
+    fn parse_unary(&mut self) -> Result<PropertyPtr, (usize, String)> {
+        if self.eat("!") {
+            return Ok(NotProperty::make_ptr(&self.parse_unary()?));
+        }
+        if self.eat("(") {
+            let prop = self.parse_or()?;
+            self.expect(")")?;
+            return Ok(prop);
+        }
+        self.parse_atom()
+    }
+
+    // kind:value where the value runs up to the next & | ( or )
+    fn parse_atom(&mut self) -> Result<PropertyPtr, (usize, String)> {
+        self.skip_space();
+        let start = self.pos;
+        while self.pos < self.chars.len() && self.chars[self.pos].is_ascii_alphabetic() {
+            self.pos += 1;
+        }
+        let kind: String = self.chars[start..self.pos].iter().collect();
+        if kind.is_empty() {
+            return self.error("expected eg type:Action or kw:+buy");
+        }
+        self.expect(":")?;
+        let value_start = self.pos;
+        while self.pos < self.chars.len() && !"&|()".contains(self.chars[self.pos]) {
+            self.pos += 1;
+        }
+        let value: String = self.chars[value_start..self.pos].iter().collect();
+        let value = value.trim();
+        if value.is_empty() {
+            self.pos = value_start;
+            return self.error(&format!("expected a value after {}:", kind));
+        }
+        Ok(match kind.as_str() {
+            "type" => TypeProperty::make_ptr(value, false),
+            "kw" | "keyword" => KeywordProperty::make_ptr(value, false),
+            "group" => CardGroupProperty::make_ptr(value),
+            "name" => NameProperty::make_ptr(&value.to_string()),
+            _ => {
+                self.pos = start;
+                return self.error(&format!(
+                    "unknown property {} (expected type, kw, group or name)",
+                    kind
+                ));
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test_expressions {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let e = parse_constraint("count(type:Action & kw:+action & !type:Attack) >= 2").unwrap();
+        assert_eq!((e.min, e.max), (2, MANY));
+        let e = parse_constraint(" count( (name:Candlestick Maker|kw:+buy) ) < 3").unwrap();
+        assert_eq!((e.min, e.max), (0, 2));
+        assert_eq!(e.text, "count( (name:Candlestick Maker|kw:+buy) ) < 3");
+        assert_eq!(
+            parse_constraint("count(colour:Red) == 1").err(),
+            Some((
+                7,
+                "unknown property colour (expected type, kw, group or name)".to_string()
+            ))
+        );
+        assert_eq!(
+            parse_constraint("count(type:Action) >= two")
+                .err()
+                .map(|e| e.0),
+            Some(23)
+        );
+        assert!(parse_constraint("count(type:Action >= 2").is_err());
+        assert!(parse_constraint("count(type:) >= 2").is_err());
+    }
+}
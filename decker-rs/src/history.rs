@@ -0,0 +1,120 @@
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::errors::{DeckerError, DeckerResult};
+use crate::selections::SelectionPtr;
+
+// Kingdoms which have been played, one per line (oldest first):
+//   <seconds since 1970><tab><pile>;<pile>;...
+// Lines starting with # are ignored.
+// Names rather than kingdom codes so the file survives changes to the card data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    pub time: u64,
+    pub piles: Vec<String>,
+}
+
+impl HistoryEntry {
+    // only the kingdom piles are worth remembering
+    pub fn new(sel: &SelectionPtr, time: u64) -> HistoryEntry {
+        let mut piles: Vec<String> = sel
+            .get_piles()
+            .iter()
+            .filter(|p| p.get_supply() && p.get_kingdom())
+            .map(|p| p.get_name().to_string())
+            .collect();
+        piles.sort();
+        HistoryEntry { time, piles }
+    }
+}
+
+pub fn parse_history(text: &str, source: &str) -> DeckerResult<Vec<HistoryEntry>> {
+    let mut res = vec![];
+    for (num, line) in text.lines().enumerate() {
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let entry = line.split_once('\t').and_then(|(time, piles)| {
+            Some(HistoryEntry {
+                time: time.parse::<u64>().ok()?,
+                piles: piles.split(';').map(|s| s.to_string()).collect(),
+            })
+        });
+        match entry {
+            Some(e) => res.push(e),
+            None => {
+                return Err(DeckerError::Parse {
+                    source: source.to_string(),
+                    line: num + 1,
+                    column: None,
+                    message: "expected time<tab>pile;pile...".to_string(),
+                })
+            }
+        }
+    }
+    Ok(res)
+}
+
+// A missing file is just an empty history
+pub fn read_history(fname: &str) -> DeckerResult<Vec<HistoryEntry>> {
+    match std::fs::read_to_string(fname) {
+        Ok(text) => parse_history(&text, fname),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(DeckerError::FileIO {
+            filename: fname.to_string(),
+            reason: e.to_string(),
+        }),
+    }
+}
+
+pub fn record_history(fname: &str, sels: &[SelectionPtr]) -> DeckerResult<()> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let mut text = String::new();
+    for s in sels {
+        let e = HistoryEntry::new(s, now);
+        text.push_str(&format!("{}\t{}\n", e.time, e.piles.join(";")));
+    }
+    let io_err = |e: std::io::Error| DeckerError::FileIO {
+        filename: fname.to_string(),
+        reason: e.to_string(),
+    };
+    let mut f = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(fname)
+        .map_err(io_err)?;
+    f.write_all(text.as_bytes()).map_err(io_err)
+}
+
+// Piles from the last `games` entries, with how many games ago they were
+// last played (1 = the most recent)
+pub fn recent_piles(history: &[HistoryEntry], games: usize) -> BTreeMap<String, usize> {
+    let mut res = BTreeMap::new();
+    for (ago, e) in history.iter().rev().take(games).enumerate() {
+        for p in &e.piles {
+            res.entry(p.clone()).or_insert(ago + 1);
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod test_history {
+    use super::*;
+
+    #[test]
+    fn test_recent() {
+        let text = "# played\n100\tVillage;Smithy\n200\tMoat;Village\n300\tCellar\n";
+        let h = parse_history(text, "test").unwrap();
+        assert_eq!(h.len(), 3);
+        let recent = recent_piles(&h, 2);
+        assert_eq!(recent.get("Cellar"), Some(&1));
+        assert_eq!(recent.get("Village"), Some(&2));
+        assert_eq!(recent.get("Smithy"), None);
+        assert!(parse_history("Village;Smithy\n", "test").is_err());
+    }
+}
@@ -0,0 +1,169 @@
+use std::fmt;
+use std::rc::Rc;
+
+use crate::costs::{decode_cost, CostTarget};
+
+// One entry from the "other interacts" column, parsed when the cards are loaded
+#[derive(Debug, Clone)]
+pub enum Interaction {
+    Card(String),
+    Group(String),
+    Item(String),
+    React(String),
+    Type(String),
+    CostTarget(Rc<dyn CostTarget>),
+    // extra(name.low.high) - an extra kingdom pile costing low..=high
+    // extra(name.low.high.Type) - the same but the pile must have Type
+    Extra {
+        name: String,
+        low: i8,
+        high: i8,
+        pile_type: Option<String>,
+    },
+    VariableCost,
+    Unknown(String),
+}
+
+impl Interaction {
+    // Err is for things which look like a known form but are broken
+    // (anything not recognised at all is Unknown)
+    pub fn parse(s: &str) -> Result<Interaction, String> {
+        let s = s.trim();
+        if s.starts_with("cost") {
+            return match decode_cost(s) {
+                Some(t) => Ok(Interaction::CostTarget(t)),
+                None => Err(format!("can't decode cost target {}", s)),
+            };
+        }
+        if s == "variable_cost" {
+            return Ok(Interaction::VariableCost);
+        }
+        let (form, rest) = match s.split_once('(') {
+            Some(v) => v,
+            None => return Ok(Interaction::Unknown(s.to_string())),
+        };
+        let arg = match rest.strip_suffix(')') {
+            Some(a) => a.to_string(),
+            None => return Err(format!("unbalanced brackets in interaction {}", s)),
+        };
+        Ok(match form {
+            "card" => Interaction::Card(arg),
+            "group" => Interaction::Group(arg),
+            "item" => Interaction::Item(arg),
+            "react" => Interaction::React(arg),
+            "type" => Interaction::Type(arg),
+            "extra" => {
+                let bad = || Err(format!("expected extra(name.low.high[.Type]) not {}", s));
+                let parts: Vec<&str> = arg.split('.').collect();
+                let (name, low, high, pile_type) = match parts[..] {
+                    [name, low, high] => (name, low, high, None),
+                    [name, low, high, t] if !t.is_empty() => (name, low, high, Some(t.to_string())),
+                    _ => return bad(),
+                };
+                match (low.parse::<i8>(), high.parse::<i8>()) {
+                    (Ok(low), Ok(high)) if !name.is_empty() && low <= high => Interaction::Extra {
+                        name: name.to_string(),
+                        low,
+                        high,
+                        pile_type,
+                    },
+                    _ => return bad(),
+                }
+            }
+            _ => Interaction::Unknown(s.to_string()),
+        })
+    }
+}
+
+// Cost targets have no equality of their own so compare their string forms
+impl PartialEq for Interaction {
+    fn eq(&self, other: &Self) -> bool {
+        use Interaction::*;
+        match (self, other) {
+            (Card(a), Card(b))
+            | (Group(a), Group(b))
+            | (Item(a), Item(b))
+            | (React(a), React(b))
+            | (Type(a), Type(b))
+            | (Unknown(a), Unknown(b)) => a == b,
+            (CostTarget(a), CostTarget(b)) => a.str_rep() == b.str_rep(),
+            (
+                Extra {
+                    name: n1,
+                    low: l1,
+                    high: h1,
+                    pile_type: t1,
+                },
+                Extra {
+                    name: n2,
+                    low: l2,
+                    high: h2,
+                    pile_type: t2,
+                },
+            ) => n1 == n2 && l1 == l2 && h1 == h2 && t1 == t2,
+            (VariableCost, VariableCost) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Interaction {}
+
+impl fmt::Display for Interaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Interaction::Card(s) => write!(f, "card({})", s),
+            Interaction::Group(s) => write!(f, "group({})", s),
+            Interaction::Item(s) => write!(f, "item({})", s),
+            Interaction::React(s) => write!(f, "react({})", s),
+            Interaction::Type(s) => write!(f, "type({})", s),
+            Interaction::CostTarget(t) => write!(f, "{}", t.str_rep()),
+            Interaction::Extra {
+                name,
+                low,
+                high,
+                pile_type,
+            } => match pile_type {
+                Some(t) => write!(f, "extra({}.{}.{}.{})", name, low, high, t),
+                None => write!(f, "extra({}.{}.{})", name, low, high),
+            },
+            Interaction::VariableCost => write!(f, "variable_cost"),
+            Interaction::Unknown(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_interactions {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Interaction::parse("card(Lucky Coin)"),
+            Ok(Interaction::Card("Lucky Coin".to_string()))
+        );
+        assert_eq!(
+            Interaction::parse("extra(bane.2.3)"),
+            Ok(Interaction::Extra {
+                name: "bane".to_string(),
+                low: 2,
+                high: 3,
+                pile_type: None
+            })
+        );
+        let mouse = Interaction::parse("extra(mouse.2.3.Action)").unwrap();
+        assert_eq!(mouse.to_string(), "extra(mouse.2.3.Action)");
+        assert!(Interaction::parse("extra(mouse.3.2)").is_err());
+        assert_eq!(
+            Interaction::parse(" item(tavernmat)"),
+            Ok(Interaction::Item("tavernmat".to_string()))
+        );
+        assert_eq!(
+            Interaction::parse("buy"),
+            Ok(Interaction::Unknown("buy".to_string()))
+        );
+        assert!(Interaction::parse("extra(bane)").is_err());
+        assert!(Interaction::parse("type(Ruins").is_err());
+    }
+}
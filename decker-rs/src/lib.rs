@@ -0,0 +1,139 @@
+//! Generates starting selections for games of Dominion.
+//!
+//! The usual flow is: load a card database into a `Config`, build a
+//! collection from its piles, shuffle it, build the constraints and then
+//! ask the collection for a selection.
+//!
+//! ```no_run
+//! use decker::{CardCollectionPtr, ConfigBuilder};
+//!
+//! let mut conf = ConfigBuilder::new()
+//!     .card_file("cards.dat")
+//!     .seed(5)
+//!     .build()
+//!     .unwrap();
+//! let mut col = CardCollectionPtr::new_state(&conf.piles);
+//! col.shuffle(&mut conf.rand);
+//! let col = CardCollectionPtr::from_state(col);
+//! let cons = conf.build_constraints(&col).unwrap();
+//! let sel = col
+//!     .generate_selection(10, conf.optional_extras, &conf.includes, &cons, &mut conf.rand)
+//!     .unwrap();
+//! for p in sel.get_piles() {
+//!     println!("{} {:?}", p.get_name(), sel.get_tags(p));
+//! }
+//! ```
+//!
+//! For output, `output::SelectionView` gives the piles sorted and split
+//! into kingdom, landscapes etc and an `output::Renderer` turns that into text.
+
+use std::fs::File;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::path::Path;
+
+pub static MAXCOINCOST: i8 = 11;
+
+pub const MANY: u64 = 5000;
+
+pub mod actions;
+pub mod bad_rand;
+pub mod cards;
+pub mod codes;
+pub mod collections;
+pub mod config;
+pub mod constraints;
+pub mod costs;
+pub mod errors;
+pub mod expressions;
+pub mod history;
+pub mod interactions;
+pub mod lint;
+pub mod output;
+pub mod piles;
+pub mod properties;
+pub mod selections;
+
+pub use cards::{load_cards, Card, CardPtr};
+pub use collections::{CardColl, CardCollectionPtr, CollectionState, CollectionStatus};
+pub use config::{Config, ConfigBuilder};
+pub use constraints::{Constraint, ConstraintPtr};
+pub use errors::{DeckerError, DeckerResult};
+pub use interactions::Interaction;
+pub use piles::{Pile, PilePtr, PileSet};
+pub use selections::{Selection, SelectionPtr};
+
+// A bunch of utility functions that will be removed later
+
+pub type StringMultiMap = std::collections::BTreeMap<String, Vec<String>>;
+
+fn short_value(s: &str) -> i8 {
+    s.parse::<i8>().unwrap_or(-1)
+}
+
+fn string_split(s: &str, sep: char) -> Vec<String> {
+    let mut v = vec![];
+    for i in s.split(sep) {
+        v.push(i.to_string());
+    }
+    v
+}
+
+pub fn read_boxes(fname: &String) -> DeckerResult<StringMultiMap> {
+    let ifs = match File::open(Path::new(fname)) {
+        Err(e) => {
+            return Err(DeckerError::FileIO {
+                filename: fname.to_string(),
+                reason: e.to_string(),
+            })
+        }
+        Ok(f) => f,
+    };
+    parse_boxes(BufReader::new(ifs), fname)
+}
+
+// source is used to label errors
+pub fn parse_boxes<R: BufRead>(input: R, source: &str) -> DeckerResult<StringMultiMap> {
+    let mut res = StringMultiMap::new();
+    for (num, item) in input.lines().enumerate() {
+        let line = match item {
+            Err(_) => break,
+            Ok(l) => l,
+        };
+        if line.starts_with('#') || line.is_empty() {
+            continue;
+        }
+        let split_eq = string_split(&line, '=');
+        if split_eq.len() != 2 || split_eq[0].is_empty() || split_eq[1].is_empty() {
+            return Err(DeckerError::Parse {
+                source: source.to_string(),
+                line: num + 1,
+                column: None,
+                message: "expected box=group;group...".to_string(),
+            });
+        }
+        let groups = string_split(&split_eq[1], ';');
+        for g in groups {
+            let e = res.entry(split_eq[0].to_string()).or_default();
+            e.push(g);
+        }
+    }
+    Ok(res)
+}
+
+// 64 bit FNV-1a, used to identify which data files a selection came from
+pub fn data_hash(bytes: &[u8]) -> u64 {
+    let mut h: u64 = 0xcbf29ce484222325;
+    for b in bytes {
+        h ^= *b as u64;
+        h = h.wrapping_mul(0x100000001b3);
+    }
+    h
+}
+
+pub fn group_name_prefix(group_name: &str) -> String {
+    match group_name.split_once('-') {
+        Some((lhs, _)) => lhs.to_string(),
+        None => group_name.to_string(),
+    }
+}
@@ -0,0 +1,464 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use crate::cards::load_cards_from;
+use crate::errors::{DeckerError, DeckerResult};
+use crate::interactions::Interaction;
+
+// Everything below is the vocabulary the rest of the program (and
+// the people reading the card file) expect to see.
+// Add new entries here when a new expansion brings new words.
+const TYPES: [&str; 39] = [
+    "Action",
+    "Treasure",
+    "Victory",
+    "Curse",
+    "Attack",
+    "Reaction",
+    "Duration",
+    "Night",
+    "Reserve",
+    "Traveller",
+    "Castle",
+    "Fate",
+    "Doom",
+    "Heirloom",
+    "Looter",
+    "Ruins",
+    "Shelter",
+    "Knight",
+    "Gathering",
+    "Prize",
+    "Reward",
+    "Spirit",
+    "Zombie",
+    "Command",
+    "Liaison",
+    "Augur",
+    "Clash",
+    "Fort",
+    "Odyssey",
+    "Townsfolk",
+    "Wizard",
+    "Event",
+    "Landmark",
+    "Project",
+    "Way",
+    "Artefact",
+    "Ally",
+    "Trait",
+    "Various", // for piles like Knights where the cards differ
+];
+
+const KEYWORDS: [&str; 24] = [
+    "+card",
+    "+action",
+    "+buy",
+    "+spend",
+    "+point",
+    "+coffers",
+    "+villager",
+    "gain",
+    "discard",
+    "topdeck",
+    "trash_any",
+    "trash_limited",
+    "reveal",
+    "curser",
+    "exile",
+    "clone",
+    "set_aside",
+    "overpay",
+    "return",
+    "upgrade",
+    "impersonate",
+    "exchange",
+    "shuffle",
+    "discount",
+];
+
+// "interacts with keywords" can also use trash to mean either kind of trashing
+const KW_INTERACTIONS: [&str; 1] = ["trash"];
+
+// Forms in "other interacts" which take an argument
+const FORMS: [&str; 6] = ["card", "group", "item", "react", "type", "extra"];
+
+const TYPES_COL: &str = "types";
+const KEYWORDS_COL: &str = "keywords";
+const KW_COL: &str = "interacts with keywords";
+const OTHER_COL: &str = "other interacts";
+const NAME_COL: &str = "Card title";
+const PILE_COL: &str = "Pile title";
+const GROUP_COL: &str = "Group";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintIssue {
+    pub line: usize,
+    pub column: usize,
+    pub card: String,
+    pub message: String,
+    pub suggestion: Option<String>,
+}
+
+impl fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: card {}: {}",
+            self.line, self.column, self.card, self.message
+        )?;
+        if let Some(s) = &self.suggestion {
+            write!(f, " (did you mean \"{}\"?)", s)?;
+        }
+        Ok(())
+    }
+}
+
+// Optimal string alignment distance (edit distance which also
+// counts swapping two neighbouring characters as one edit)
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, v) in d[0].iter_mut().enumerate() {
+        *v = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            let mut v = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                v = v.min(d[i - 2][j - 2] + 1);
+            }
+            d[i][j] = v;
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+// Closest word in vocab, if there is one close enough to be a typo
+fn near_miss<'a, I>(word: &str, vocab: I) -> Option<String>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let lower = word.to_lowercase();
+    let limit = if word.chars().count() <= 4 { 1 } else { 2 };
+    let mut best: Option<(usize, &str)> = None;
+    for v in vocab {
+        let d = edit_distance(&lower, &v.to_lowercase());
+        if d <= limit && best.is_none_or(|(bd, _)| d < bd) {
+            best = Some((d, v));
+        }
+    }
+    best.map(|(_, v)| v.to_string())
+}
+
+// Two known words run together, eg +card+spend or Treasure’Attack
+fn run_together(word: &str, vocab: &[&str]) -> Option<String> {
+    for (i, _) in word.char_indices().skip(1) {
+        let (lhs, rhs) = word.split_at(i);
+        let rhs = match rhs.chars().next() {
+            Some(c) if !c.is_alphanumeric() && c != '+' && c != '_' => &rhs[c.len_utf8()..],
+            _ => rhs,
+        };
+        if vocab.contains(&lhs) && vocab.contains(&rhs) {
+            return Some(format!("{};{}", lhs, rhs));
+        }
+    }
+    None
+}
+
+// Check one word against a vocabulary.
+// Returns None if the word is fine, otherwise the message and a suggestion.
+fn check_word(word: &str, what: &str, vocab: &[&str]) -> Option<(String, Option<String>)> {
+    if vocab.contains(&word) {
+        return None;
+    }
+    if word.is_empty() {
+        return Some((format!("empty {} in list", what), None));
+    }
+    if let Some(v) = vocab.iter().find(|v| v.eq_ignore_ascii_case(word)) {
+        return Some((
+            format!("{} {} has the wrong case", what, word),
+            Some(v.to_string()),
+        ));
+    }
+    if let Some(s) = run_together(word, vocab) {
+        return Some((
+            format!("{} {} looks like two entries run together", what, word),
+            Some(s),
+        ));
+    }
+    let mut message = format!("unknown {} {}", what, word);
+    if !word.is_ascii() {
+        message += " (contains non-ASCII characters)";
+    }
+    Some((message, near_miss(word, vocab.iter().copied())))
+}
+
+struct Linter {
+    card_names: BTreeSet<String>,
+    groups: BTreeSet<String>,
+    issues: Vec<LintIssue>,
+    line: usize,
+    column: usize,
+    card: String,
+}
+
+impl Linter {
+    fn report(&mut self, message: String, suggestion: Option<String>) {
+        self.issues.push(LintIssue {
+            line: self.line,
+            column: self.column,
+            card: self.card.clone(),
+            message,
+            suggestion,
+        });
+    }
+
+    // Split a list on any of seps, reporting whitespace problems.
+    // Gives back the trimmed entries.
+    fn split_list(&mut self, cell: &str, seps: &[char]) -> Vec<String> {
+        if cell.is_empty() {
+            return vec![];
+        }
+        let mut res = vec![];
+        for s in cell.split(seps) {
+            let t = s.trim();
+            if t != s {
+                self.report(
+                    format!("stray whitespace in \"{}\"", s),
+                    Some(t.to_string()),
+                );
+            }
+            res.push(t.to_string());
+        }
+        res
+    }
+
+    fn check_list(&mut self, cell: &str, what: &str, vocab: &[&str]) {
+        for w in self.split_list(cell, &[';']) {
+            if let Some((message, suggestion)) = check_word(&w, what, vocab) {
+                self.report(message, suggestion);
+            }
+        }
+    }
+
+    // the loader also splits these on commas (in a quoted cell)
+    fn check_other(&mut self, cell: &str, react_vocab: &[&str]) {
+        for s in self.split_list(cell, &[';', ',']) {
+            let it = match Interaction::parse(&s) {
+                Ok(i) => i,
+                Err(message) => {
+                    self.report(message, None);
+                    continue;
+                }
+            };
+            match it {
+                Interaction::Card(a) | Interaction::Group(a) | Interaction::Item(a)
+                    if a.is_empty() =>
+                {
+                    self.report(format!("empty argument in {}", s), None)
+                }
+                Interaction::Card(a) => {
+                    if !self.card_names.contains(&a) {
+                        let miss = near_miss(&a, self.card_names.iter().map(|s| s.as_str()));
+                        let suggestion = miss.map(|m| format!("card({})", m));
+                        self.report(format!("card({}) names an unknown card", a), suggestion);
+                    }
+                }
+                Interaction::Group(a) => {
+                    if !self.groups.contains(&a) {
+                        let miss = near_miss(&a, self.groups.iter().map(|s| s.as_str()));
+                        let suggestion = miss.map(|m| format!("group({})", m));
+                        self.report(format!("group({}) names an unknown group", a), suggestion);
+                    }
+                }
+                Interaction::Type(a) => {
+                    // type(Spoils) means the Spoils pile
+                    if let Some((message, suggestion)) = check_word(&a, "type", &TYPES) {
+                        if !self.card_names.contains(&a) {
+                            let suggestion = suggestion.map(|t| format!("type({})", t));
+                            self.report(message, suggestion);
+                        }
+                    }
+                }
+                Interaction::React(a) => {
+                    if let Some((message, suggestion)) =
+                        check_word(&a, "reaction target", react_vocab)
+                    {
+                        let suggestion = suggestion.map(|t| format!("react({})", t));
+                        self.report(message, suggestion);
+                    }
+                }
+                Interaction::Unknown(u) => {
+                    let form = u.split('(').next().unwrap_or("");
+                    if FORMS.contains(&form) {
+                        self.report(format!("{} needs an argument eg {}(...)", form, form), None)
+                    } else {
+                        let suggestion = near_miss(form, FORMS.iter().copied());
+                        self.report(format!("unknown interaction form {}", u), suggestion);
+                    }
+                }
+                Interaction::Extra {
+                    pile_type: Some(t), ..
+                } => {
+                    if let Some((message, suggestion)) = check_word(&t, "type", &TYPES) {
+                        self.report(message, suggestion);
+                    }
+                }
+                Interaction::Item(_)
+                | Interaction::CostTarget(_)
+                | Interaction::Extra { .. }
+                | Interaction::VariableCost => {}
+            }
+        }
+    }
+}
+
+pub fn lint_cards(card_filename: &String) -> DeckerResult<Vec<LintIssue>> {
+    let ifs = match File::open(Path::new(card_filename)) {
+        Err(e) => {
+            return Err(DeckerError::FileIO {
+                filename: card_filename.to_string(),
+                reason: e.to_string(),
+            })
+        }
+        Ok(f) => f,
+    };
+    lint_cards_from(BufReader::new(ifs), card_filename)
+}
+
+// Check every row of a card file against the vocabulary.
+// Anything the loader itself rejects comes back as an error instead.
+pub fn lint_cards_from<R: Read>(mut input: R, source: &str) -> DeckerResult<Vec<LintIssue>> {
+    let mut text = String::new();
+    if let Err(e) = input.read_to_string(&mut text) {
+        return Err(DeckerError::FileIO {
+            filename: source.to_string(),
+            reason: e.to_string(),
+        });
+    }
+    load_cards_from(text.as_bytes(), source, &mut HashMap::new(), &mut vec![])?;
+
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let headers = match reader.headers() {
+        Ok(h) => h.clone(),
+        Err(e) => {
+            return Err(DeckerError::Parse {
+                source: source.to_string(),
+                line: 1,
+                column: None,
+                message: e.to_string(),
+            })
+        }
+    };
+    let column_of = |name: &str| headers.iter().position(|h| h == name);
+    let records: Vec<csv::StringRecord> = reader.records().filter_map(|r| r.ok()).collect();
+    let cell = |r: &csv::StringRecord, name: &str| -> String {
+        column_of(name)
+            .and_then(|i| r.get(i))
+            .unwrap_or("")
+            .to_string()
+    };
+
+    let mut linter = Linter {
+        card_names: BTreeSet::new(),
+        groups: BTreeSet::new(),
+        issues: vec![],
+        line: 0,
+        column: 0,
+        card: String::new(),
+    };
+    // card() can refer to piles as well as cards
+    for r in &records {
+        for col in [NAME_COL, PILE_COL] {
+            let s = cell(r, col);
+            if !s.is_empty() {
+                linter.card_names.insert(s);
+            }
+        }
+        linter.groups.insert(cell(r, GROUP_COL));
+    }
+    let mut react_vocab: Vec<&str> = TYPES.to_vec();
+    react_vocab.extend(KEYWORDS);
+    react_vocab.extend(KW_INTERACTIONS);
+    let mut kw_vocab: Vec<&str> = KEYWORDS.to_vec();
+    kw_vocab.extend(KW_INTERACTIONS);
+
+    for r in &records {
+        linter.card = cell(r, NAME_COL);
+        if linter.card.is_empty() {
+            continue;
+        }
+        linter.line = r.position().map_or(0, |p| p.line() as usize);
+        for col in [TYPES_COL, KEYWORDS_COL, KW_COL, OTHER_COL] {
+            linter.column = column_of(col).map_or(0, |i| i + 1);
+            let value = cell(r, col);
+            match col {
+                TYPES_COL => linter.check_list(&value, "type", &TYPES),
+                KEYWORDS_COL => linter.check_list(&value, "keyword", &KEYWORDS),
+                KW_COL => linter.check_list(&value, "keyword", &kw_vocab),
+                _ => linter.check_other(&value, &react_vocab),
+            }
+        }
+    }
+    Ok(linter.issues)
+}
+
+#[cfg(test)]
+mod test_lint {
+    use super::*;
+
+    const HEADER: &str = "Card title,Pile title,Group,in supply,is kingdom,types,cost,spending power,debt,potion cost,Points,keywords,interacts with keywords,other interacts,comments\n";
+
+    fn suggestions(rows: &str) -> Vec<Option<String>> {
+        let text = format!("{}{}", HEADER, rows);
+        lint_cards_from(text.as_bytes(), "test")
+            .unwrap()
+            .into_iter()
+            .map(|i| i.suggestion)
+            .collect()
+    }
+
+    #[test]
+    fn test_clean_row() {
+        assert!(suggestions("Village,,Core,y,y,Action,3,,,,,+action;+card,,,\n").is_empty());
+    }
+
+    #[test]
+    fn test_near_misses() {
+        let s = |v: &str| Some(v.to_string());
+        assert_eq!(
+            suggestions(
+                "A,,Core,y,y,prize;Artefect,3,,,,,disacrd;+card+spend; discard,,type(attack),\n"
+            ),
+            vec![
+                s("Prize"),
+                s("Artefact"),
+                s("discard"), // whitespace is reported while splitting
+                s("discard"),
+                s("+card;+spend"),
+                s("type(Attack)"),
+            ]
+        );
+        assert_eq!(
+            suggestions("Village,,Core,y,y,Treasure’Attack,3,,,,,,,card(Vilage),\n"),
+            vec![s("Treasure;Attack"), s("card(Village)")]
+        );
+        assert!(suggestions(
+            "Fool,,Nocturne,y,y,Action;Fate,3,,,,,,,\"group(Nocturne),card(Fool)\",\n"
+        )
+        .is_empty());
+    }
+}
@@ -1,231 +1,95 @@
-use std::fs::File;
-use std::io::BufRead;
-use std::io::BufReader;
-use std::path::Path;
-
 use std::process::exit;
 
 use clap::Parser;
 
-static MAXCOINCOST: i8 = 11;
-
-const MANY: u64 = 5000;
-
-mod actions;
-mod bad_rand;
-mod cards;
-mod collections;
-mod config;
-mod constraints;
-mod costs;
-mod piles;
-mod properties;
-mod selections;
-
-use collections::{CardCollectionPtr, CollectionStatus};
-use config::load_config;
+use decker::history::record_history;
+use decker::lint::lint_cards;
+use decker::output::{
+    HtmlRenderer, JsonRenderer, MarkdownRenderer, Renderer, SelectionView, TextRenderer,
+};
+use decker::{CardCollectionPtr, CollectionStatus, DeckerError, DeckerResult};
 
-// A bunch of utility functions that will be removed later
-
-type StringMultiMap = std::collections::BTreeMap<String, Vec<String>>;
-
-fn short_value(s: &str) -> i8 {
-    s.parse::<i8>().unwrap_or(-1)
-}
+mod cli;
 
-fn bool_value(s: &str) -> bool {
-    s == "Y" || s == "y"
-}
+use cli::{Cli, Command, Format};
 
-fn string_split(s: &str, sep: char) -> Vec<String> {
-    let mut v = vec![];
-    for i in s.split(sep) {
-        v.push(i.to_string());
+// Problems go to stdout, the summary (and exit code) come from the error
+fn lint(card_file: &str) -> DeckerResult<()> {
+    let issues = lint_cards(&card_file.to_string())?;
+    for i in &issues {
+        println!("{}:{}", card_file, i);
     }
-    v
-}
-
-fn no_empty_split(s: &String, sep: char) -> Vec<String> {
-    if s.is_empty() {
-        return Vec::<String>::new();
+    match issues.len() {
+        0 => Ok(()),
+        n => Err(DeckerError::Lint(n)),
     }
-    string_split(s, sep)
 }
 
-fn read_boxes(fname: &String) -> Result<StringMultiMap, String> {
-    let ifs = match File::open(Path::new(fname)) {
-        Err(_) => return Err("Can't open file".to_string()),
-        Ok(f) => f,
-    };
-    let input = BufReader::new(ifs);
-    let mut res = StringMultiMap::new();
-    for (num, item) in input.lines().enumerate() {
-        let line = match item {
-            Err(_) => break,
-            Ok(l) => l,
-        };
-        if line.starts_with('#') || line.is_empty() {
-            continue;
-        }
-        let split_eq = string_split(&line, '=');
-        if split_eq.len() != 2 || split_eq[0].is_empty() || split_eq[1].is_empty() {
-            return Err(format!("Can't parse line {}", num));
-        }
-        let groups = string_split(&split_eq[1], ';');
-        for g in groups {
-            let e = res.entry(split_eq[0].to_string()).or_default();
-            e.push(g);
-        }
+fn run(cli: Cli) -> DeckerResult<()> {
+    if let Some(Command::Lint) = cli.get_command() {
+        return lint(cli.get_card_file("cards.dat"));
     }
-    Ok(res)
-}
-
-fn group_name_prefix(group_name: &str) -> String {
-    match group_name.split_once('-') {
-        Some((lhs, _)) => lhs.to_string(),
-        None => group_name.to_string(),
+    let mut conf = cli.to_builder("cards.dat")?.build()?;
+    for w in &conf.warnings {
+        eprintln!("Warning: {}", w);
+    }
+    for (filter, removed) in &conf.filter_counts {
+        eprintln!("{} removed {} piles", filter, removed);
     }
-}
-
-#[derive(Parser)]
-pub struct Cli {
-    /// Seed for random number generator.
-    #[arg(long)]
-    seed: Option<u64>,
-
-    /// Use bad (but cross platform) random number generator
-    #[arg(long)]
-    badrand: bool,
-
-    /// Which boxes to include in the collection.
-    #[arg(long, value_delimiter = ',')]
-    boxes: Vec<String>,
-
-    /// Which groups to include in the collection.
-    #[arg(long, value_delimiter = ',')]
-    groups: Vec<String>,
-
-    /// Filename listing boxes and which groups they contain
-    #[arg(long)]
-    boxfile: Option<String>,
-
-    /// Filename listing all cards.
-    #[arg(long)]
-    cardfile: Option<String>,
-
-    /// Dump contents of collection and exit.
-    #[arg(long)]
-    list: bool,
-
-    /// How many landscape cards to include (does not include artefacts etc).
-    #[arg(long)]
-    landscape_count: Option<u8>,
-
-    /// Explain why cards were added.
-    #[arg(long)]
-    why: bool,
-
-    /// Do not validate collection.
-    #[arg(long)]
-    no_validate: bool,
-
-    /// Do not allow any of these cards.
-    #[arg(long, value_delimiter = ',')]
-    exclude: Vec<String>,
-
-    /// This card must be in the selection.
-    #[arg(long, value_delimiter = ',')]
-    include: Vec<String>,
-
-    /// Show info about selected cards.
-    #[arg(long)]
-    info: bool,
-
-    /// Disable automatic adding reacts to attacks.
-    #[arg(long)]
-    no_attack_react: bool,
-
-    /// Disable automatic adding of trash cards if cards give curses.
-    #[arg(long)]
-    no_anti_cursor: bool,
-
-    /// Set the maximum number of times a cost can occur.
-    #[arg(long, default_value_t = 0)]
-    max_cost_repeat: u8,
-
-    /// eg --min-type=Treasure:5 means that the selection will can contain at least 5 treasures."));
-    #[arg(long, value_delimiter = ',')]
-    min_type: Vec<String>,
-
-    /// eg --max-type=Treasure:5 means that the selection will can contain at most 5 treasures."));
-    #[arg(long, value_delimiter = ',')]
-    max_type: Vec<String>,
-
-    /// Most prefixes (groups and related groups) which can be included. Eg: Cornucopia would also allow Cornucopia-prizes.
-    #[arg(long, default_value_t = 0)]
-    max_prefixes: u8,
-}
-
-fn main() {
-    let cli = Cli::parse();
-
-    let mut conf = match load_config(cli, "cards.dat".to_string(), "".to_string()) {
-        Ok(v) => v,
-        Err(e) => {
-            println!("{}", e);
-            exit(1);
-        }
-    };
 
     // Need to create the state separately, list, validate and sort
 
-    let mut col = CardCollectionPtr::new_state(&conf.piles);
+    let col = CardCollectionPtr::new_state(&conf.piles);
     if conf.validate {
-        let warnings = match col.validate_collection() {
-            CollectionStatus::CollOK => {
-                vec![]
-            }
-            CollectionStatus::CollWarning(v) => v,
-            CollectionStatus::_CollFatal(v) => v,
-        };
-        if !warnings.is_empty() {
-            println!("Error validating collection:");
-            for s in warnings {
-                println!("{}", s);
-            }
-            exit(3);
+        match col.validate_collection() {
+            CollectionStatus::CollOK => {}
+            CollectionStatus::CollWarning(v) => return Err(DeckerError::Validation(v)),
+            CollectionStatus::CollFatal(v) => return Err(DeckerError::Validation(v)),
         };
     };
     if conf.list_collection {
         for p in &conf.piles {
             println!("{}", p.get_name());
         }
-        exit(0);
+        return Ok(());
     };
-    col.shuffle(&mut conf.rand);
-    let col = CardCollectionPtr::from_state(col);
-    let constraints = match conf.build_constraints(&col) {
-        Ok(v) => v,
-        Err(s) => {
-            println!("{}", s);
-            exit(4);
-        }
-    };
-    let sel = match col.generate_selection(
-        10,
-        conf.optional_extras,
-        &conf.includes,
-        &constraints,
-        &mut conf.rand,
-    ) {
-        Ok(s) => s,
-        Err(m) => {
-            eprintln!("Error: empty selection");
-            eprintln!("Possible explanation: {}", m);
-            exit(2);
-        }
+    let mut problems = vec![];
+    let mut views = vec![];
+    let mut sels = vec![];
+    for (sel, p) in conf.make_selections(col)? {
+        views.push(
+            SelectionView::new(&sel)
+                .with_run(&conf)
+                .with_code(&conf.code_book.encode(&sel))
+                .with_problems(&p),
+        );
+        sels.push(sel);
+        problems.extend(p);
+    }
+    let renderer: Box<dyn Renderer> = match cli.get_format() {
+        Format::Text => Box::new(TextRenderer {
+            // when completing a kingdom the reasons are the point
+            show_all: conf.why || conf.complete.is_some(),
+            show_card_info: conf.more_info,
+        }),
+        Format::Json => Box::new(JsonRenderer {}),
+        Format::Markdown => Box::new(MarkdownRenderer {}),
+        Format::Html => Box::new(HtmlRenderer {}),
     };
-    // TODO: Have to come up with another way to do this, as I'm removing get-string
-    // println!("Options:{}", conf.get_string());
-    sel.dump(conf.why, conf.more_info);
+    print!("{}", renderer.render_all(&views));
+    if let Some(fname) = &conf.history_file {
+        record_history(fname, &sels)?;
+    }
+    match DeckerError::from_list(problems) {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+// Exit codes are listed with DeckerError
+fn main() {
+    if let Err(e) = run(Cli::parse()) {
+        eprintln!("{}", e);
+        exit(e.exit_code());
+    }
 }
@@ -0,0 +1,685 @@
+use std::fmt::Write;
+
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::costs::Cost;
+use crate::errors::DeckerError;
+use crate::piles::{PilePtr, SortablePile};
+use crate::selections::SelectionPtr;
+
+// Bump this if the json layout changes in a way which could break readers
+pub const JSON_VERSION: u32 = 1;
+
+// Where a pile goes when the selection is laid out
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PileKind {
+    Kingdom,
+    Landscape,
+    // eg Spoils, Horse, Shelters
+    NonSupply,
+    // supply piles which aren't kingdom piles eg Copper, Potion, Ruins
+    Base,
+}
+
+// Everything a renderer needs to know about one pile
+#[derive(Clone, Debug)]
+pub struct PileView {
+    pub name: String,
+    pub group: String,
+    pub kind: PileKind,
+    pub types: Vec<String>,
+    pub costs: Vec<Cost>,
+    // in the order they were added, including <why...> reasons
+    pub tags: Vec<String>,
+}
+
+impl PileView {
+    fn new(sel: &SelectionPtr, p: &PilePtr) -> PileView {
+        let kind = if p.get_supply() && p.get_kingdom() {
+            PileKind::Kingdom
+        } else if p.get_supply() {
+            PileKind::Base
+        } else if p.is_landscape() {
+            PileKind::Landscape
+        } else {
+            PileKind::NonSupply
+        };
+        let mut types: Vec<String> = p.get_types().iter().cloned().collect();
+        types.sort();
+        let mut costs: Vec<Cost> = p.get_costs().iter().cloned().collect();
+        costs.sort();
+        PileView {
+            name: p.get_name().to_string(),
+            group: p.get_card_group().to_string(),
+            kind,
+            types,
+            costs,
+            tags: sel.get_tags(p),
+        }
+    }
+
+    // tags meant for players eg Bane
+    pub fn visible_tags(&self) -> Vec<&str> {
+        self.tags
+            .iter()
+            .filter(|s| !s.contains('<'))
+            .map(|s| s.as_str())
+            .collect()
+    }
+
+    // <why...> explanations of why the pile was picked
+    pub fn reasons(&self) -> Vec<&str> {
+        self.tags
+            .iter()
+            .filter(|s| s.contains('<'))
+            .map(|s| s.as_str())
+            .collect()
+    }
+}
+
+// How the selection was made
+#[derive(Clone, Debug)]
+pub struct RunInfo {
+    pub seed: u64,
+    pub rng: String,
+    pub options: String,
+    // "Options: ..." line
+    pub header: String,
+}
+
+impl RunInfo {
+    pub fn new(conf: &Config) -> RunInfo {
+        RunInfo {
+            seed: conf.seed,
+            rng: conf.rand_kind.get_name().to_string(),
+            options: conf.options.clone(),
+            header: conf.header.clone(),
+        }
+    }
+}
+
+// Sorted (by group then name) view of a selection for renderers
+// (and library users who want to lay things out themselves)
+#[derive(Clone, Debug)]
+pub struct SelectionView {
+    pub piles: Vec<PileView>,
+    pub notes: Vec<String>,
+    pub items: Vec<String>,
+    pub run: Option<RunInfo>,
+    // kingdom code (see codes.rs)
+    pub code: Option<String>,
+    // constraints the selection doesn't meet (only from complete_selection)
+    pub problems: Vec<String>,
+}
+
+impl SelectionView {
+    pub fn new(sel: &SelectionPtr) -> SelectionView {
+        let mut piles: Vec<SortablePile> = sel
+            .get_piles()
+            .iter()
+            .map(|p| SortablePile { p: p.clone() })
+            .collect();
+        piles.sort();
+        SelectionView {
+            piles: piles.iter().map(|sp| PileView::new(sel, &sp.p)).collect(),
+            notes: sel.get_notes().iter().cloned().collect(),
+            items: sel.get_items().into_iter().collect(),
+            run: None,
+            code: None,
+            problems: vec![],
+        }
+    }
+
+    pub fn with_run(mut self, conf: &Config) -> SelectionView {
+        self.run = Some(RunInfo::new(conf));
+        self
+    }
+
+    pub fn with_code(mut self, code: &str) -> SelectionView {
+        self.code = Some(code.to_string());
+        self
+    }
+
+    pub fn with_problems(mut self, problems: &[DeckerError]) -> SelectionView {
+        self.problems = problems.iter().map(|e| e.to_string()).collect();
+        self
+    }
+
+    pub fn piles_of(&self, kind: PileKind) -> impl Iterator<Item = &PileView> {
+        self.piles.iter().filter(move |p| p.kind == kind)
+    }
+}
+
+impl SelectionPtr {
+    // Prints the selection in the text format (without the Options: header)
+    pub fn dump(&self, show_all: bool, show_card_info: bool) {
+        let r = TextRenderer {
+            show_all,
+            show_card_info,
+        };
+        print!("{}", r.render(&SelectionView::new(self)));
+    }
+}
+
+pub trait Renderer {
+    fn render(&self, view: &SelectionView) -> String;
+
+    // for --count, by default the selections one after the other
+    fn render_all(&self, views: &[SelectionView]) -> String {
+        let all: Vec<String> = views.iter().map(|v| self.render(v)).collect();
+        all.join("\n")
+    }
+}
+
+// The original terminal layout
+pub struct TextRenderer {
+    // include <why...> tags
+    pub show_all: bool,
+    pub show_card_info: bool,
+}
+
+impl Renderer for TextRenderer {
+    fn render(&self, view: &SelectionView) -> String {
+        let mut out = String::new();
+        if let Some(run) = &view.run {
+            let _ = writeln!(out, "{}", run.header);
+        }
+        let max_len = view.piles.iter().map(|p| p.name.len()).max().unwrap_or(0);
+        let mut group_name = "";
+        for p in &view.piles {
+            if p.group != group_name {
+                group_name = &p.group;
+                let _ = writeln!(out, "From {}", group_name);
+            }
+            let _ = write!(out, "   {}", p.name);
+            let tags: Vec<&str> = if self.show_all {
+                p.tags.iter().map(|s| s.as_str()).collect()
+            } else {
+                p.visible_tags()
+            };
+            if !tags.is_empty() {
+                let _ = write!(out, " ({})", tags.join(", "));
+            }
+            if self.show_card_info {
+                let costs: Vec<String> = p.costs.iter().map(|c| c.get_string()).collect();
+                let _ = write!(
+                    out,
+                    "{:pad$} types={} costs={{{}}}",
+                    "",
+                    p.types.join(", "),
+                    costs.join(", "),
+                    pad = max_len - p.name.len()
+                );
+            }
+            out.push('\n');
+        }
+        if !view.items.is_empty() {
+            out.push_str("Need the following items:\n");
+            for s in &view.items {
+                let _ = writeln!(out, "   {}", s);
+            }
+        }
+        if !view.problems.is_empty() {
+            out.push_str("Problems:\n");
+            for s in &view.problems {
+                let _ = writeln!(out, "   {}", s);
+            }
+        }
+        if let Some(code) = &view.code {
+            let _ = writeln!(out, "Kingdom code: {}", code);
+        }
+        out
+    }
+}
+
+#[derive(Serialize)]
+struct PileJson<'a> {
+    name: &'a str,
+    group: &'a str,
+    types: &'a [String],
+    costs: &'a [Cost],
+    tags: Vec<&'a str>,
+    reasons: Vec<&'a str>,
+}
+
+#[derive(Serialize)]
+struct SelectionJson<'a> {
+    version: u32,
+    seed: Option<u64>,
+    rng: Option<&'a str>,
+    options: Option<&'a str>,
+    code: Option<&'a str>,
+    kingdom: Vec<PileJson<'a>>,
+    landscapes: Vec<PileJson<'a>>,
+    non_supply: Vec<PileJson<'a>>,
+    base: Vec<PileJson<'a>>,
+    notes: &'a [String],
+    items: &'a [String],
+    problems: &'a [String],
+}
+
+// Stable layout for other programs, see SelectionJson
+pub struct JsonRenderer {}
+
+impl JsonRenderer {
+    fn to_json(view: &SelectionView) -> SelectionJson<'_> {
+        let piles = |kind| {
+            view.piles_of(kind)
+                .map(|p| PileJson {
+                    name: &p.name,
+                    group: &p.group,
+                    types: &p.types,
+                    costs: &p.costs,
+                    tags: p.visible_tags(),
+                    reasons: p.reasons(),
+                })
+                .collect()
+        };
+        SelectionJson {
+            version: JSON_VERSION,
+            seed: view.run.as_ref().map(|r| r.seed),
+            rng: view.run.as_ref().map(|r| r.rng.as_str()),
+            options: view.run.as_ref().map(|r| r.options.as_str()),
+            code: view.code.as_deref(),
+            kingdom: piles(PileKind::Kingdom),
+            landscapes: piles(PileKind::Landscape),
+            non_supply: piles(PileKind::NonSupply),
+            base: piles(PileKind::Base),
+            notes: &view.notes,
+            items: &view.items,
+            problems: &view.problems,
+        }
+    }
+}
+
+impl Renderer for JsonRenderer {
+    fn render(&self, view: &SelectionView) -> String {
+        // can't fail, everything in here is strings and numbers
+        let mut out =
+            serde_json::to_string_pretty(&JsonRenderer::to_json(view)).unwrap_or_default();
+        out.push('\n');
+        out
+    }
+
+    // a list of selections so the output is still one json value
+    fn render_all(&self, views: &[SelectionView]) -> String {
+        if views.len() == 1 {
+            return self.render(&views[0]);
+        }
+        let all: Vec<SelectionJson> = views.iter().map(JsonRenderer::to_json).collect();
+        let mut out = serde_json::to_string_pretty(&all).unwrap_or_default();
+        out.push('\n');
+        out
+    }
+}
+
+// What goes on a setup sheet, shared by the Markdown and HTML layouts
+struct SetupSheet<'a> {
+    // (cost label, piles) cheapest first
+    kingdom: Vec<(String, Vec<&'a PileView>)>,
+    landscapes: Vec<&'a PileView>,
+    non_supply: Vec<&'a PileView>,
+    base: Vec<&'a PileView>,
+    starting_deck: Vec<String>,
+}
+
+impl<'a> SetupSheet<'a> {
+    fn new(view: &'a SelectionView) -> SetupSheet<'a> {
+        let mut kingdom: Vec<&PileView> = view.piles_of(PileKind::Kingdom).collect();
+        kingdom.sort_by(|a, b| {
+            a.costs
+                .first()
+                .cmp(&b.costs.first())
+                .then(a.name.cmp(&b.name))
+        });
+        let mut by_cost: Vec<(String, Vec<&PileView>)> = vec![];
+        for p in kingdom {
+            let label = p.costs.first().map_or("$0".to_string(), |c| c.label());
+            match by_cost.last_mut() {
+                Some((l, v)) if *l == label => v.push(p),
+                _ => by_cost.push((label, vec![p])),
+            }
+        }
+        let has_type = |p: &PileView, t: &str| p.types.iter().any(|s| s == t);
+        let mut starting_deck = vec![];
+        let shelters: Vec<&str> = view
+            .piles
+            .iter()
+            .filter(|p| has_type(p, "Shelter"))
+            .map(|p| p.name.as_str())
+            .collect();
+        if !shelters.is_empty() {
+            starting_deck.push(format!(
+                "Shelters: start with {} instead of 3 Estates",
+                shelters.join(", ")
+            ));
+        }
+        for p in view.piles.iter().filter(|p| has_type(p, "Heirloom")) {
+            starting_deck.push(format!(
+                "Heirloom: start with {} instead of 1 Copper",
+                p.name
+            ));
+        }
+        SetupSheet {
+            kingdom: by_cost,
+            landscapes: view.piles_of(PileKind::Landscape).collect(),
+            non_supply: view
+                .piles_of(PileKind::NonSupply)
+                .filter(|p| !has_type(p, "Shelter") && !has_type(p, "Heirloom"))
+                .collect(),
+            base: view.piles_of(PileKind::Base).collect(),
+            starting_deck,
+        }
+    }
+}
+
+// name (Bane) with the visible tags
+fn pile_label(p: &PileView) -> String {
+    let tags = p.visible_tags();
+    if tags.is_empty() {
+        p.name.clone()
+    } else {
+        format!("{} ({})", p.name, tags.join(", "))
+    }
+}
+
+fn footer(view: &SelectionView) -> Vec<String> {
+    let mut res = vec![];
+    if let Some(r) = &view.run {
+        res.push(format!("decker {} # rng={}", r.options, r.rng));
+    }
+    if let Some(code) = &view.code {
+        res.push(format!("Kingdom code: {}", code));
+    }
+    res
+}
+
+pub struct MarkdownRenderer {}
+
+impl Renderer for MarkdownRenderer {
+    fn render(&self, view: &SelectionView) -> String {
+        let sheet = SetupSheet::new(view);
+        let mut out = String::from("# Dominion setup\n\n## Kingdom\n");
+        for (cost, piles) in &sheet.kingdom {
+            let _ = writeln!(out, "\n### {}\n", cost);
+            for p in piles {
+                let _ = writeln!(out, "- **{}** ({})", pile_label(p), p.group);
+            }
+        }
+        let mut list = |title: &str, lines: Vec<String>| {
+            if !lines.is_empty() {
+                let _ = writeln!(out, "\n## {}\n", title);
+                for l in lines {
+                    let _ = writeln!(out, "{}", l);
+                }
+            }
+        };
+        let names = |piles: &[&PileView]| -> Vec<String> {
+            piles
+                .iter()
+                .map(|p| format!("- {} ({}; {})", pile_label(p), p.group, p.types.join(", ")))
+                .collect()
+        };
+        list("Landscapes", names(&sheet.landscapes));
+        list("Non-supply piles", names(&sheet.non_supply));
+        list(
+            "Starting deck changes",
+            sheet
+                .starting_deck
+                .iter()
+                .map(|s| format!("- {}", s))
+                .collect(),
+        );
+        list(
+            "Other supply piles",
+            vec![sheet
+                .base
+                .iter()
+                .map(|p| pile_label(p))
+                .collect::<Vec<_>>()
+                .join(", ")],
+        );
+        list(
+            "Items",
+            view.items.iter().map(|s| format!("- [ ] {}", s)).collect(),
+        );
+        list(
+            "Problems",
+            view.problems.iter().map(|s| format!("- {}", s)).collect(),
+        );
+        let lines = footer(view);
+        if !lines.is_empty() {
+            out.push_str("\n---\n");
+            for l in lines {
+                let _ = writeln!(out, "\n`{}`", l);
+            }
+        }
+        out
+    }
+}
+
+fn html_escape(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => res.push_str("&amp;"),
+            '<' => res.push_str("&lt;"),
+            '>' => res.push_str("&gt;"),
+            '"' => res.push_str("&quot;"),
+            '\'' => res.push_str("&#39;"),
+            _ => res.push(c),
+        }
+    }
+    res
+}
+
+const HTML_STYLE: &str = "body{font-family:sans-serif;max-width:50em;margin:auto}\
+.cost{display:flex;gap:1em;align-items:baseline}\
+.cost h3{min-width:3em}\
+.card{border:1px solid #888;border-radius:4px;padding:.3em .6em}\
+.group{color:#666;font-size:small}\
+footer{margin-top:2em;color:#666;font-family:monospace}";
+
+// Self-contained page (no external css or scripts) for printing
+pub struct HtmlRenderer {}
+
+impl HtmlRenderer {
+    fn page(sheets: &[String]) -> String {
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>Dominion setup</title>\n<style>{}</style>\n</head>\n<body>\n\
+             {}</body>\n</html>\n",
+            HTML_STYLE,
+            sheets.join("<hr>\n")
+        )
+    }
+
+    fn sheet(view: &SelectionView) -> String {
+        let sheet = SetupSheet::new(view);
+        let mut out = String::from("<h1>Dominion setup</h1>\n<h2>Kingdom</h2>\n");
+        for (cost, piles) in &sheet.kingdom {
+            let _ = write!(out, "<div class=\"cost\"><h3>{}</h3>", html_escape(cost));
+            for p in piles {
+                let _ = write!(
+                    out,
+                    "<div class=\"card\">{}<div class=\"group\">{}</div></div>",
+                    html_escape(&pile_label(p)),
+                    html_escape(&p.group)
+                );
+            }
+            out.push_str("</div>\n");
+        }
+        let mut list = |title: &str, lines: Vec<String>| {
+            if !lines.is_empty() {
+                let _ = writeln!(out, "<h2>{}</h2>\n<ul>", title);
+                for l in lines {
+                    let _ = writeln!(out, "<li>{}</li>", l);
+                }
+                out.push_str("</ul>\n");
+            }
+        };
+        let names = |piles: &[&PileView]| -> Vec<String> {
+            piles
+                .iter()
+                .map(|p| {
+                    format!(
+                        "{} <span class=\"group\">{}; {}</span>",
+                        html_escape(&pile_label(p)),
+                        html_escape(&p.group),
+                        html_escape(&p.types.join(", "))
+                    )
+                })
+                .collect()
+        };
+        list("Landscapes", names(&sheet.landscapes));
+        list("Non-supply piles", names(&sheet.non_supply));
+        list(
+            "Starting deck changes",
+            sheet.starting_deck.iter().map(|s| html_escape(s)).collect(),
+        );
+        list(
+            "Other supply piles",
+            sheet
+                .base
+                .iter()
+                .map(|p| html_escape(&pile_label(p)))
+                .collect(),
+        );
+        list(
+            "Items",
+            view.items
+                .iter()
+                .map(|s| {
+                    format!(
+                        "<label><input type=\"checkbox\"> {}</label>",
+                        html_escape(s)
+                    )
+                })
+                .collect(),
+        );
+        list(
+            "Problems",
+            view.problems.iter().map(|s| html_escape(s)).collect(),
+        );
+        let lines = footer(view);
+        if !lines.is_empty() {
+            let lines: Vec<String> = lines.iter().map(|l| html_escape(l)).collect();
+            let _ = writeln!(out, "<footer>{}</footer>", lines.join("<br>"));
+        }
+        out
+    }
+}
+
+impl Renderer for HtmlRenderer {
+    fn render(&self, view: &SelectionView) -> String {
+        HtmlRenderer::page(&[HtmlRenderer::sheet(view)])
+    }
+
+    // one page so it prints in one go
+    fn render_all(&self, views: &[SelectionView]) -> String {
+        HtmlRenderer::page(&views.iter().map(HtmlRenderer::sheet).collect::<Vec<_>>())
+    }
+}
+
+#[cfg(test)]
+mod test_output {
+    use super::*;
+    use crate::collections::CardCollectionPtr;
+    use crate::ConfigBuilder;
+
+    // a selection from the real card data, including a landscape
+    fn view(seed: u64) -> SelectionView {
+        let mut conf = ConfigBuilder::new()
+            .card_file("../dat/cards.dat")
+            .seed(seed)
+            .include(&["Young Witch", "Way of the Mouse"])
+            .build()
+            .unwrap();
+        let col = CardCollectionPtr::new_state(&conf.piles);
+        let (sel, _) = conf.make_selection(col).unwrap();
+        SelectionView::new(&sel)
+            .with_run(&conf)
+            .with_code(&conf.code_book.encode(&sel))
+    }
+
+    #[test]
+    fn test_text() {
+        let v = view(7);
+        let out = TextRenderer {
+            show_all: false,
+            show_card_info: false,
+        }
+        .render(&v);
+        let lines: Vec<&str> = out.lines().collect();
+        assert!(lines[0].starts_with("Options: --seed=7 "));
+        assert!(lines[1].starts_with("From "));
+        assert_eq!(
+            lines.last().unwrap().to_string(),
+            format!("Kingdom code: {}", v.code.as_ref().unwrap())
+        );
+        assert!(out.contains("   Way of the Mouse\n"));
+        assert!(out.contains(" (Bane)\n"));
+        assert!(!out.contains("<why"));
+    }
+
+    #[test]
+    fn test_json() {
+        let v = view(7);
+        let out = JsonRenderer {}.render(&v);
+        let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(json["version"], JSON_VERSION);
+        assert_eq!(json["seed"], 7);
+        assert_eq!(json["rng"], "xoshiro256**");
+        assert_eq!(json["code"], v.code.unwrap().as_str());
+        // ten plus the bane and the pile for Way of the Mouse
+        let kingdom = json["kingdom"].as_array().unwrap();
+        assert_eq!(kingdom.len(), 12);
+        assert!(kingdom
+            .iter()
+            .any(|p| p["tags"].as_array().unwrap().contains(&"Bane".into())));
+        assert!(kingdom
+            .iter()
+            .all(|p| p["name"].is_string() && p["costs"].is_array() && p["reasons"].is_array()));
+        assert!(json["landscapes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|p| p["name"] == "Way of the Mouse"));
+        assert_eq!(json["problems"], serde_json::json!([]));
+        // several selections are a list
+        let two = JsonRenderer {}.render_all(&[view(7), view(8)]);
+        let json: serde_json::Value = serde_json::from_str(&two).unwrap();
+        assert_eq!(json[1]["seed"], 8);
+    }
+
+    #[test]
+    fn test_markdown() {
+        let out = MarkdownRenderer {}.render(&view(7));
+        assert!(out.starts_with("# Dominion setup\n\n## Kingdom\n"));
+        // the bane costs 2 or 3
+        assert!(out.contains("\n### $2\n") || out.contains("\n### $3\n"));
+        assert!(out.contains("\n## Landscapes\n\n- Way of the Mouse (Menagerie-ways; Way)\n"));
+        assert!(out.contains("\n## Other supply piles\n"));
+        assert!(out.contains("\n`decker --seed=7 "));
+    }
+
+    #[test]
+    fn test_html() {
+        let mut v = view(7);
+        v.items.push("<script>".to_string());
+        v.problems.push("Fish & \"Chips\"".to_string());
+        let out = HtmlRenderer {}.render(&v);
+        assert!(out.starts_with("<!DOCTYPE html>\n"));
+        assert!(out.ends_with("</html>\n"));
+        assert!(out.contains("&lt;script&gt;"));
+        assert!(!out.contains("<script>"));
+        assert!(out.contains("<li>Fish &amp; &quot;Chips&quot;</li>"));
+        assert!(out.contains("<h2>Landscapes</h2>"));
+        // one page for several selections
+        let two = HtmlRenderer {}.render_all(&[view(7), view(8)]);
+        assert_eq!(two.matches("<!DOCTYPE html>").count(), 1);
+        assert_eq!(two.matches("<h1>Dominion setup</h1>").count(), 2);
+    }
+}
@@ -7,6 +7,7 @@ use std::rc::Rc;
 
 use crate::cards::{Card, Cards};
 use crate::costs::{CostSet, CostTarget};
+use crate::interactions::Interaction;
 
 #[derive(Debug)]
 pub struct Pile {
@@ -18,9 +19,9 @@ pub struct Pile {
     costs: CostSet,
     keywords: HashSet<String>,
     kw_interactions: HashSet<String>,
-    other_interactions: HashSet<String>,
+    other_interactions: Vec<Interaction>,
     cards: Cards,
-    targets: Vec<Box<dyn CostTarget>>,
+    targets: Vec<Rc<dyn CostTarget>>,
 }
 
 pub type PilePtr = Rc<Pile>;
@@ -62,13 +63,13 @@ impl Pile {
             costs: CostSet::new(),
             keywords: HashSet::new(),
             kw_interactions: HashSet::new(),
-            other_interactions: HashSet::new(),
+            other_interactions: Vec::new(),
             cards: Cards::new(),
             targets: Vec::new(),
         }
     }
 
-    fn add_cost_target(&mut self, new_target: &Box<dyn CostTarget>) {
+    fn add_cost_target(&mut self, new_target: &Rc<dyn CostTarget>) {
         for t in &self.targets {
             if t.str_rep() == new_target.str_rep() {
                 return;
@@ -99,14 +100,16 @@ impl Pile {
             self.kw_interactions.insert(t.to_string());
         }
         for t in c.get_other_interactions() {
-            self.other_interactions.insert(t.to_string());
+            if let Interaction::CostTarget(ct) = t {
+                self.add_cost_target(ct);
+            }
+            if !self.other_interactions.contains(t) {
+                self.other_interactions.push(t.clone());
+            }
         }
         self.card_group = c.get_card_group().to_string();
         self.supply = c.get_supply() || self.supply;
         self.kingdom = c.get_kingdom() || self.kingdom;
-        for t in &c.cost_targets {
-            self.add_cost_target(t);
-        }
         self.cards.push(Rc::new(c));
     }
 
@@ -133,16 +136,24 @@ impl Pile {
         &self.kw_interactions
     }
     // In list of things I use, note a lot of string sets
-    pub fn get_other_interactions(&self) -> &HashSet<String> {
+    pub fn get_other_interactions(&self) -> &[Interaction] {
         &self.other_interactions
     }
     pub fn get_name(&self) -> &str {
         &self.name
     }
+    // Events, Projects etc which are chosen as optional extras
+    pub fn is_landscape(&self) -> bool {
+        !self.supply
+            && !self.kingdom
+            && ["Event", "Project", "Landmark", "Way"]
+                .iter()
+                .any(|t| self.types.contains(*t))
+    }
     pub fn get_cards(&self) -> &Cards {
         &self.cards
     }
-    pub(crate) fn get_targets(&self) -> &Vec<Box<dyn CostTarget>> {
+    pub(crate) fn get_targets(&self) -> &Vec<Rc<dyn CostTarget>> {
         &self.targets
     }
 }
@@ -159,7 +170,7 @@ impl PartialEq for SortablePile {
     fn eq(&self, other: &SortablePile) -> bool {
         // can do this because no cards should have
         // the same name but different groups
-        return self.p.get_name() == other.p.get_name();
+        self.p.get_name() == other.p.get_name()
     }
 }
 
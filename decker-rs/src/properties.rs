@@ -3,6 +3,8 @@ use std::hash::{Hash, Hasher};
 use std::rc::Rc;
 
 use crate::costs::{Cost, CostSet};
+use crate::group_name_prefix;
+use crate::interactions::Interaction;
 use crate::piles::PilePtr;
 use crate::selections::SelectionPtr;
 use std::collections::hash_map::Entry::Occupied;
@@ -10,12 +12,13 @@ use std::collections::hash_map::Entry::Occupied;
 // Re-export all these, so I don't have to use them individually
 pub mod prelude {
     pub use super::{
-        CardGroupProperty, CostAndTypeProperty, CostProperty, EitherProperty, FailProperty,
-        HangingInteractsWith, KeywordInteractionProperty, KeywordProperty,
-        KingdomAndSupplyProperty, MissingGroupForKeywordProperty,
-        MissingInteractingCardGroupProperty, MissingInteractingCardProperty, MissingPotionProperty,
-        NameProperty, NeedProsperity, NoteProperty, OptionalExtraProperty,
-        OtherInteractionProperty, RepeatedCostProperty, TypeProperty,
+        AllProperty, AnyProperty, CardGroupProperty, CostAndTypeProperty, CostProperty,
+        EitherProperty, FailProperty, GroupPrefixProperty, HalfPairProperty, HangingInteractsWith,
+        KeywordInteractionProperty, KeywordProperty, KingdomAndSupplyProperty,
+        MissingGroupForKeywordProperty, MissingInteractingCardGroupProperty,
+        MissingInteractingCardProperty, MissingInteractingTypeProperty, MissingPotionProperty,
+        NameProperty, NeedProsperity, NotProperty, NoteProperty, OptionalExtraProperty,
+        OtherInteractionProperty, PairProperty, RepeatedCostProperty, TypeProperty,
     };
 }
 
@@ -35,7 +38,7 @@ pub struct PropertyPtr {
 
 impl PartialEq for PropertyPtr {
     fn eq(&self, other: &Self) -> bool {
-        Rc::as_ptr(&self.state) == Rc::as_ptr(&other.state)
+        Rc::ptr_eq(&self.state, &other.state)
     }
 }
 
@@ -43,7 +46,7 @@ impl Eq for PropertyPtr {}
 
 impl Hash for PropertyPtr {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        Rc::as_ptr(&self.state).hash(state);
+        (Rc::as_ptr(&self.state) as *const ()).hash(state);
     }
 }
 
@@ -52,11 +55,11 @@ impl PropertyPtr {
         self.state.is_selection_property()
     }
 
-    pub(crate) fn pile_meets(&self, p: &PilePtr) -> bool {
+    pub fn pile_meets(&self, p: &PilePtr) -> bool {
         self.state.pile_meets(p)
     }
 
-    pub(crate) fn selection_meets(&self, s: &SelectionPtr) -> bool {
+    pub fn selection_meets(&self, s: &SelectionPtr) -> bool {
         self.state.selection_meets(s)
     }
 }
@@ -64,7 +67,7 @@ impl PropertyPtr {
 pub struct KingdomAndSupplyProperty {}
 
 impl KingdomAndSupplyProperty {
-    pub(crate) fn make_ptr() -> PropertyPtr {
+    pub fn make_ptr() -> PropertyPtr {
         PropertyPtr {
             state: Rc::new(KingdomAndSupplyProperty {}),
         }
@@ -91,7 +94,7 @@ pub struct TypeProperty {
 }
 
 impl TypeProperty {
-    pub(crate) fn make_ptr(has_type: &str, restrict_to_kingdom_and_supply: bool) -> PropertyPtr {
+    pub fn make_ptr(has_type: &str, restrict_to_kingdom_and_supply: bool) -> PropertyPtr {
         PropertyPtr {
             state: Rc::new(TypeProperty {
                 type_name: has_type.to_owned(),
@@ -110,7 +113,7 @@ impl Property for TypeProperty {
         if self.kingdom_and_supply && (!p.get_kingdom() || !p.get_supply()) {
             return false;
         }
-        return p.get_types().contains(&self.type_name);
+        p.get_types().contains(&self.type_name)
     }
 
     fn selection_meets(&self, _s: &SelectionPtr) -> bool {
@@ -123,7 +126,7 @@ pub struct NameProperty {
 }
 
 impl NameProperty {
-    pub(crate) fn make_ptr(name: &String) -> PropertyPtr {
+    pub fn make_ptr(name: &String) -> PropertyPtr {
         PropertyPtr {
             state: Rc::new(NameProperty {
                 name: name.to_string(),
@@ -152,7 +155,7 @@ pub struct CostAndTypeProperty {
 }
 
 impl CostAndTypeProperty {
-    pub(crate) fn make_ptr_set(type_name: String, cost: CostSet) -> PropertyPtr {
+    pub fn make_ptr_set(type_name: String, cost: CostSet) -> PropertyPtr {
         PropertyPtr {
             state: Rc::new(CostAndTypeProperty {
                 cost_prop: CostProperty::make_ptr_set(cost, true),
@@ -184,7 +187,7 @@ pub struct NoteProperty {
 }
 
 impl NoteProperty {
-    pub(crate) fn make_ptr(text: &String) -> PropertyPtr {
+    pub fn make_ptr(text: &String) -> PropertyPtr {
         PropertyPtr {
             state: Rc::new(NoteProperty {
                 text: text.to_string(),
@@ -213,7 +216,7 @@ pub struct EitherProperty {
 }
 
 impl EitherProperty {
-    pub(crate) fn make_ptr(prop1: &PropertyPtr, prop2: &PropertyPtr) -> PropertyPtr {
+    pub fn make_ptr(prop1: &PropertyPtr, prop2: &PropertyPtr) -> PropertyPtr {
         PropertyPtr {
             state: Rc::new(EitherProperty {
                 prop1: prop1.clone(),
@@ -237,12 +240,96 @@ impl Property for EitherProperty {
     }
 }
 
+// Meets all of the properties (and is vacuously true for none)
+pub struct AllProperty {
+    props: Vec<PropertyPtr>,
+}
+
+impl AllProperty {
+    pub fn make_ptr(props: &[PropertyPtr]) -> PropertyPtr {
+        PropertyPtr {
+            state: Rc::new(AllProperty {
+                props: props.to_vec(),
+            }),
+        }
+    }
+}
+
+impl Property for AllProperty {
+    fn is_selection_property(&self) -> bool {
+        self.props.iter().any(|p| p.is_selection_property())
+    }
+
+    fn pile_meets(&self, p: &PilePtr) -> bool {
+        self.props.iter().all(|prop| prop.pile_meets(p))
+    }
+
+    fn selection_meets(&self, s: &SelectionPtr) -> bool {
+        self.props.iter().all(|prop| prop.selection_meets(s))
+    }
+}
+
+// n-ary version of EitherProperty
+pub struct AnyProperty {
+    props: Vec<PropertyPtr>,
+}
+
+impl AnyProperty {
+    pub fn make_ptr(props: &[PropertyPtr]) -> PropertyPtr {
+        PropertyPtr {
+            state: Rc::new(AnyProperty {
+                props: props.to_vec(),
+            }),
+        }
+    }
+}
+
+impl Property for AnyProperty {
+    fn is_selection_property(&self) -> bool {
+        self.props.iter().any(|p| p.is_selection_property())
+    }
+
+    fn pile_meets(&self, p: &PilePtr) -> bool {
+        self.props.iter().any(|prop| prop.pile_meets(p))
+    }
+
+    fn selection_meets(&self, s: &SelectionPtr) -> bool {
+        self.props.iter().any(|prop| prop.selection_meets(s))
+    }
+}
+
+pub struct NotProperty {
+    prop: PropertyPtr,
+}
+
+impl NotProperty {
+    pub fn make_ptr(prop: &PropertyPtr) -> PropertyPtr {
+        PropertyPtr {
+            state: Rc::new(NotProperty { prop: prop.clone() }),
+        }
+    }
+}
+
+impl Property for NotProperty {
+    fn is_selection_property(&self) -> bool {
+        self.prop.is_selection_property()
+    }
+
+    fn pile_meets(&self, p: &PilePtr) -> bool {
+        !self.prop.pile_meets(p)
+    }
+
+    fn selection_meets(&self, s: &SelectionPtr) -> bool {
+        !self.prop.selection_meets(s)
+    }
+}
+
 pub struct CardGroupProperty {
     group_name: String,
 }
 
 impl CardGroupProperty {
-    pub(crate) fn make_ptr(group_name: &str) -> PropertyPtr {
+    pub fn make_ptr(group_name: &str) -> PropertyPtr {
         PropertyPtr {
             state: Rc::new(CardGroupProperty {
                 group_name: group_name.to_owned(),
@@ -265,10 +352,39 @@ impl Property for CardGroupProperty {
     }
 }
 
+// Kingdom piles from any group with this prefix (eg Seaside or DarkAges)
+pub struct GroupPrefixProperty {
+    prefix: String,
+}
+
+impl GroupPrefixProperty {
+    pub fn make_ptr(prefix: &str) -> PropertyPtr {
+        PropertyPtr {
+            state: Rc::new(GroupPrefixProperty {
+                prefix: prefix.to_owned(),
+            }),
+        }
+    }
+}
+
+impl Property for GroupPrefixProperty {
+    fn is_selection_property(&self) -> bool {
+        false
+    }
+
+    fn pile_meets(&self, p: &PilePtr) -> bool {
+        p.get_kingdom() && p.get_supply() && group_name_prefix(p.get_card_group()) == self.prefix
+    }
+
+    fn selection_meets(&self, _s: &SelectionPtr) -> bool {
+        false
+    }
+}
+
 pub struct OptionalExtraProperty {}
 
 impl OptionalExtraProperty {
-    pub(crate) fn make_ptr() -> PropertyPtr {
+    pub fn make_ptr() -> PropertyPtr {
         PropertyPtr {
             state: Rc::new(OptionalExtraProperty {}),
         }
@@ -281,12 +397,7 @@ impl Property for OptionalExtraProperty {
     }
 
     fn pile_meets(&self, p: &PilePtr) -> bool {
-        return !p.get_supply()
-            && !p.get_kingdom()
-            && (p.get_types().contains("Event")
-                || p.get_types().contains("Project")
-                || p.get_types().contains("Landmark")
-                || p.get_types().contains("Way"));
+        p.is_landscape()
     }
 
     fn selection_meets(&self, _s: &SelectionPtr) -> bool {
@@ -295,15 +406,15 @@ impl Property for OptionalExtraProperty {
 }
 
 pub struct OtherInteractionProperty {
-    other_interact: String,
+    other_interact: Interaction,
     kingdom_and_supply: bool,
 }
 
 impl OtherInteractionProperty {
-    pub(crate) fn make_ptr(other_interact: &str, kingdom_and_supply: bool) -> PropertyPtr {
+    pub fn make_ptr(other_interact: Interaction, kingdom_and_supply: bool) -> PropertyPtr {
         PropertyPtr {
             state: Rc::new(OtherInteractionProperty {
-                other_interact: other_interact.to_owned(),
+                other_interact,
                 kingdom_and_supply,
             }),
         }
@@ -330,7 +441,7 @@ impl Property for OtherInteractionProperty {
 pub struct MissingPotionProperty {}
 
 impl MissingPotionProperty {
-    pub(crate) fn make_ptr() -> PropertyPtr {
+    pub fn make_ptr() -> PropertyPtr {
         PropertyPtr {
             state: Rc::new(MissingPotionProperty {}),
         }
@@ -365,13 +476,77 @@ impl Property for MissingPotionProperty {
     }
 }
 
+// Both piles of the pair are in the selection
+pub struct PairProperty {
+    first: String,
+    second: String,
+}
+
+impl PairProperty {
+    pub fn make_ptr(first: &str, second: &str) -> PropertyPtr {
+        PropertyPtr {
+            state: Rc::new(PairProperty {
+                first: first.to_owned(),
+                second: second.to_owned(),
+            }),
+        }
+    }
+}
+
+impl Property for PairProperty {
+    fn is_selection_property(&self) -> bool {
+        true
+    }
+
+    fn pile_meets(&self, _p: &PilePtr) -> bool {
+        false
+    }
+
+    fn selection_meets(&self, s: &SelectionPtr) -> bool {
+        let has = |name: &str| s.get_piles().iter().any(|p| p.get_name() == name);
+        has(&self.first) && has(&self.second)
+    }
+}
+
+// Exactly one pile of the pair is in the selection
+pub struct HalfPairProperty {
+    first: String,
+    second: String,
+}
+
+impl HalfPairProperty {
+    pub fn make_ptr(first: &str, second: &str) -> PropertyPtr {
+        PropertyPtr {
+            state: Rc::new(HalfPairProperty {
+                first: first.to_owned(),
+                second: second.to_owned(),
+            }),
+        }
+    }
+}
+
+impl Property for HalfPairProperty {
+    fn is_selection_property(&self) -> bool {
+        true
+    }
+
+    fn pile_meets(&self, _p: &PilePtr) -> bool {
+        false
+    }
+
+    fn selection_meets(&self, s: &SelectionPtr) -> bool {
+        let has = |name: &str| s.get_piles().iter().any(|p| p.get_name() == name);
+        has(&self.first) != has(&self.second)
+    }
+}
+
 pub struct MissingGroupForKeywordProperty {
     type_needed: String,
     note: String,
 }
 
 impl MissingGroupForKeywordProperty {
-    pub(crate) fn make_ptr(type_needed: &String, group_needed: &String) -> PropertyPtr {
+    pub fn make_ptr(type_needed: &String, group_needed: &String) -> PropertyPtr {
         PropertyPtr {
             state: Rc::new(MissingGroupForKeywordProperty {
                 type_needed: type_needed.to_string(),
@@ -405,7 +580,7 @@ impl Property for MissingGroupForKeywordProperty {
 pub struct MissingInteractingCardGroupProperty {}
 
 impl MissingInteractingCardGroupProperty {
-    pub(crate) fn make_ptr() -> PropertyPtr {
+    pub fn make_ptr() -> PropertyPtr {
         PropertyPtr {
             state: Rc::new(MissingInteractingCardGroupProperty {}),
         }
@@ -424,9 +599,7 @@ impl Property for MissingInteractingCardGroupProperty {
     fn selection_meets(&self, s: &SelectionPtr) -> bool {
         for p in s.get_piles() {
             for it in p.get_other_interactions() {
-                if it.starts_with("group(") {
-                    let glen = "group(".len();
-                    let need_name = &it[glen..it.len() - ')'.len_utf8()];
+                if let Interaction::Group(need_name) = it {
                     if !s.has_note(&format!("added{}", need_name)) {
                         return true;
                     }
@@ -440,7 +613,7 @@ impl Property for MissingInteractingCardGroupProperty {
 pub struct MissingInteractingCardProperty {}
 
 impl MissingInteractingCardProperty {
-    pub(crate) fn make_ptr() -> PropertyPtr {
+    pub fn make_ptr() -> PropertyPtr {
         PropertyPtr {
             state: Rc::new(MissingInteractingCardProperty {}),
         }
@@ -460,9 +633,7 @@ impl Property for MissingInteractingCardProperty {
         let mut need: BTreeSet<String> = BTreeSet::new();
         for p in s.get_piles() {
             for it in p.get_other_interactions() {
-                if it.starts_with("card(") {
-                    let prefix_len = "card(".len();
-                    let need_name = &it[prefix_len..it.len() - ')'.len_utf8()];
+                if let Interaction::Card(need_name) = it {
                     need.insert(need_name.to_string());
                 }
             }
@@ -486,10 +657,66 @@ impl Property for MissingInteractingCardProperty {
     }
 }
 
+// Non-kingdom piles which are needed because a pile in the selection
+// has type(...) but nothing in the selection has that type.
+// Types which kingdom piles also have (eg type(Action)) are left to
+// the normal selection process, so only things like Ruins get added.
+// Some "types" are really a pile name (Spoils cards are just Treasures)
+// so a pile with that name counts too.
+// Gives back (pile to add, name of the pile which needs it).
+pub(crate) fn missing_type_piles(s: &SelectionPtr) -> Vec<(PilePtr, String)> {
+    let mut res: Vec<(PilePtr, String)> = vec![];
+    let all_piles = s.get_collection().get_piles();
+    for p in s.get_piles() {
+        for it in p.get_other_interactions() {
+            if let Interaction::Type(type_name) = it {
+                let has_type =
+                    |q: &PilePtr| q.get_types().contains(type_name) || q.get_name() == type_name;
+                if s.get_piles().iter().any(has_type)
+                    || all_piles.iter().any(|q| q.get_kingdom() && has_type(q))
+                {
+                    continue;
+                }
+                for q in all_piles {
+                    if !q.get_kingdom() && has_type(q) && !res.iter().any(|(r, _)| r == q) {
+                        res.push((q.clone(), p.get_name().to_string()));
+                    }
+                }
+            }
+        }
+    }
+    res
+}
+
+pub struct MissingInteractingTypeProperty {}
+
+impl MissingInteractingTypeProperty {
+    pub fn make_ptr() -> PropertyPtr {
+        PropertyPtr {
+            state: Rc::new(MissingInteractingTypeProperty {}),
+        }
+    }
+}
+
+impl Property for MissingInteractingTypeProperty {
+    fn is_selection_property(&self) -> bool {
+        true
+    }
+
+    fn pile_meets(&self, _p: &PilePtr) -> bool {
+        false
+    }
+
+    // only counts as missing if there is something we could add
+    fn selection_meets(&self, s: &SelectionPtr) -> bool {
+        !missing_type_piles(s).is_empty()
+    }
+}
+
 pub struct FailProperty {}
 
 impl FailProperty {
-    pub(crate) fn make_ptr() -> PropertyPtr {
+    pub fn make_ptr() -> PropertyPtr {
         PropertyPtr {
             state: Rc::new(FailProperty {}),
         }
@@ -515,7 +742,7 @@ pub struct RepeatedCostProperty {
 }
 
 impl RepeatedCostProperty {
-    pub(crate) fn make_ptr(max_repeats: u64) -> PropertyPtr {
+    pub fn make_ptr(max_repeats: u64) -> PropertyPtr {
         PropertyPtr {
             state: Rc::new(RepeatedCostProperty { max_repeats }),
         }
@@ -559,7 +786,7 @@ pub struct CostProperty {
 }
 
 impl CostProperty {
-    pub(crate) fn make_ptr_set(costs: CostSet, supply_only: bool) -> PropertyPtr {
+    pub fn make_ptr_set(costs: CostSet, supply_only: bool) -> PropertyPtr {
         PropertyPtr {
             state: Rc::new(CostProperty {
                 single_cost: None,
@@ -585,7 +812,7 @@ impl Property for CostProperty {
         // we need to find if there is a non-empty intersection
         // between the cost sets. I'm not using std::set_intersection
         // because I don't need to construct the intersection
-        return Cost::intersects(p.get_costs(), &self.costs);
+        Cost::intersects(p.get_costs(), &self.costs)
     }
 
     fn selection_meets(&self, _s: &SelectionPtr) -> bool {
@@ -600,7 +827,7 @@ pub struct HangingInteractsWith {
 }
 
 impl HangingInteractsWith {
-    pub(crate) fn make_ptr2(interacts_with: &String, kw: &String) -> PropertyPtr {
+    pub fn make_ptr2(interacts_with: &String, kw: &String) -> PropertyPtr {
         PropertyPtr {
             state: Rc::new(HangingInteractsWith {
                 interacts_with: interacts_with.to_string(),
@@ -610,7 +837,7 @@ impl HangingInteractsWith {
         }
     }
 
-    pub(crate) fn make_ptr3(interacts_with: &String, kw: &String, alt_kw: &String) -> PropertyPtr {
+    pub fn make_ptr3(interacts_with: &String, kw: &String, alt_kw: &String) -> PropertyPtr {
         PropertyPtr {
             state: Rc::new(HangingInteractsWith {
                 interacts_with: interacts_with.to_string(),
@@ -653,7 +880,7 @@ pub struct KeywordProperty {
 }
 
 impl KeywordProperty {
-    pub(crate) fn make_ptr(keyword: &str, restrict_to_kingdom_and_supply: bool) -> PropertyPtr {
+    pub fn make_ptr(keyword: &str, restrict_to_kingdom_and_supply: bool) -> PropertyPtr {
         PropertyPtr {
             state: Rc::new(KeywordProperty {
                 keyword: keyword.to_owned(),
@@ -672,7 +899,7 @@ impl Property for KeywordProperty {
         if self.kingdom_and_supply && (!p.get_kingdom() || !p.get_supply()) {
             return false;
         }
-        return p.get_keywords().contains(&self.keyword);
+        p.get_keywords().contains(&self.keyword)
     }
 
     fn selection_meets(&self, _s: &SelectionPtr) -> bool {
@@ -692,7 +919,7 @@ impl Property for KeywordInteractionProperty {
     }
 
     fn pile_meets(&self, p: &PilePtr) -> bool {
-        return p.get_kw_interactions().contains(&self.keyword);
+        p.get_kw_interactions().contains(&self.keyword)
     }
 
     fn selection_meets(&self, _s: &SelectionPtr) -> bool {
@@ -705,7 +932,7 @@ pub struct NeedProsperity {
 }
 
 impl NeedProsperity {
-    pub(crate) fn make_ptr(threshold: u8) -> PropertyPtr {
+    pub fn make_ptr(threshold: u8) -> PropertyPtr {
         PropertyPtr {
             state: Rc::new(NeedProsperity { threshold }),
         }
@@ -759,3 +986,29 @@ pub struct PropLists {
     pub(crate) lists: Vec<Vec<PilePtr>>,
     pub(crate) map: HashMap<PropertyPtr, usize>,
 }
+
+#[cfg(test)]
+mod test_properties {
+    use crate::collections::CardCollectionPtr;
+    use crate::ConfigBuilder;
+
+    #[test]
+    fn test_missing_types() {
+        let mut conf = ConfigBuilder::new()
+            .card_file("../dat/cards.dat")
+            .seed(1)
+            .complete(&["Bandit Camp", "Marauder", "Village"])
+            .build()
+            .unwrap();
+        let col = CardCollectionPtr::new_state(&conf.piles);
+        let (sel, _) = conf.make_selection(col).unwrap();
+        let tags = |name: &str| {
+            let p = sel.get_piles().iter().find(|p| p.get_name() == name);
+            assert!(p.is_some(), "{} missing", name);
+            sel.get_tags(p.unwrap())
+        };
+        // Spoils is found by pile name, Ruins by type
+        assert!(tags("Spoils").contains(&"<why?type:Bandit Camp needs it>".to_string()));
+        assert!(tags("Ruins").contains(&"<why?type:Marauder needs it>".to_string()));
+    }
+}
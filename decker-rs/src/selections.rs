@@ -7,7 +7,8 @@ use crate::cards::Cards;
 use crate::collections::{CardCollectionPtr, CollectionIterator};
 use crate::constraints::ConstraintPtr;
 use crate::costs::{CostSet, CostTarget};
-use crate::piles::{PilePtr, Piles, SortablePile};
+use crate::interactions::Interaction;
+use crate::piles::{PilePtr, Piles};
 
 pub struct SelectionState {
     piles: Piles,
@@ -22,7 +23,7 @@ pub struct SelectionState {
     // This one needs to be modified after wrapping
     target_check_required: RefCell<bool>,
     target_blame: RefCell<String>, // piles responsible for cost target
-    targets: Vec<Box<dyn CostTarget>>,
+    targets: Vec<Rc<dyn CostTarget>>,
     interacts_keywords: BTreeMap<String, u64>,
     keywords: BTreeMap<String, u64>,
     card_coll: CardCollectionPtr,
@@ -43,7 +44,7 @@ impl SelectionState {
         c_vec.borrow_mut().push(cp);
     }
 
-    // only use so far is to make space for "bane" card
+    // makes space for extra piles like the "bane" card
     pub(crate) fn increase_required_piles(&mut self) {
         self.required_cards += 1
     }
@@ -92,14 +93,9 @@ impl SelectionState {
             let e = self.interacts_keywords.entry(ikw.clone()).or_insert(0);
             *e += 1;
         }
-        for react in p.get_other_interactions() {
-            if react.find("react(") == Some(0) {
-                let len1 = "react(".len();
-                let len2 = ")".len();
-                let e = self
-                    .interacts_keywords
-                    .entry(react[len1..react.len() - len2].to_string())
-                    .or_insert(0);
+        for it in p.get_other_interactions() {
+            if let Interaction::React(react) = it {
+                let e = self.interacts_keywords.entry(react.clone()).or_insert(0);
                 *e += 1;
             }
         }
@@ -198,6 +194,9 @@ pub struct SelectionPtr {
     pub(crate) state: Rc<SelectionState>,
 }
 
+// What library users get back from a generation run
+pub type Selection = SelectionPtr;
+
 impl SelectionPtr {
     pub(crate) fn from_state(s: SelectionState) -> SelectionPtr {
         SelectionPtr { state: Rc::new(s) }
@@ -227,70 +226,26 @@ impl SelectionPtr {
         }
     }
 
-    pub(crate) fn dump(&self, show_all: bool, show_card_info: bool) {
-        let mut result: Vec<SortablePile> = vec![];
-        result.reserve(self.state.piles.len());
-        let mut max_len: usize = 0;
-        for p in &self.state.piles {
-            result.push(SortablePile { p: p.clone() });
-            let l = p.get_name().len();
-            if max_len < l {
-                max_len = l;
-            }
+    // Tags in the order they were added, including <why...> reasons
+    pub fn get_tags(&self, p: &PilePtr) -> Vec<String> {
+        match self.state.tags.borrow().get(p) {
+            Some(v) => v.clone(),
+            None => vec![],
         }
-        result.sort();
-        let mut group_name = "".to_string();
-        let mut items: BTreeSet<String> = BTreeSet::new();
+    }
 
-        for pp in &result {
-            let p = &pp.p;
-            if p.get_card_group() != group_name {
-                group_name = p.get_card_group().to_string();
-                println!("From {}", group_name);
-            }
-            print!("   {}", p.get_name());
-            if let Some(e) = self.state.tags.borrow().get(p) {
-                let mut first = true;
-                for s in e {
-                    if show_all || !s.contains('<') {
-                        print!("{}{}", if first { " (" } else { ", " }, s);
-                        first = false;
-                    }
-                }
-                if !first {
-                    print!(")");
-                }
-            };
-            if show_card_info {
-                for _ in p.get_name().len()..max_len {
-                    print!(" ");
-                }
-                print!(" types=");
-                let mut first = true;
-                for s in p.get_types() {
-                    if !first {
-                        print!(", ");
-                    }
-                    first = false;
-                    print!("{}", s)
-                }
-                print!(" costs={{");
-                first = true;
-                for c in p.get_costs() {
-                    if !first {
-                        print!(", ");
-                    }
-                    first = false;
-                    print!("{}", c.get_string());
-                }
-                print!("}}");
-            }
-            println!();
-            for s in p.get_other_interactions() {
-                if s.starts_with("item(") {
-                    let l1 = "item(".len();
-                    let l2 = s.len() - ')'.len_utf8();
-                    items.insert(s[l1..l2].to_string());
+    pub fn get_notes(&self) -> &BTreeSet<String> {
+        &self.state.notes
+    }
+
+    // Items needed to play, both from the finishing checks
+    // and from item() interactions on the chosen piles
+    pub fn get_items(&self) -> BTreeSet<String> {
+        let mut items: BTreeSet<String> = BTreeSet::new();
+        for p in &self.state.piles {
+            for it in p.get_other_interactions() {
+                if let Interaction::Item(s) = it {
+                    items.insert(s.to_string());
                 }
             }
         }
@@ -298,35 +253,30 @@ impl SelectionPtr {
         for i in its {
             items.insert(i.to_string());
         }
-        if !items.is_empty() {
-            println!("Need the following items:");
-            for s in items {
-                println!("   {}", s);
-            }
-        };
+        items
     }
 
-    pub(crate) fn get_normal_pile_count(&self) -> u8 {
+    pub fn get_normal_pile_count(&self) -> u8 {
         self.state.current_normal_pile_count
     }
 
-    pub(crate) fn get_required_count(&self) -> u8 {
+    pub fn get_required_count(&self) -> u8 {
         self.state.required_cards
     }
 
-    pub(crate) fn contains(&self, p: &PilePtr) -> bool {
+    pub fn contains(&self, p: &PilePtr) -> bool {
         self.state.contains(p)
     }
 
-    pub(crate) fn get_piles(&self) -> &Piles {
+    pub fn get_piles(&self) -> &Piles {
         &self.state.piles
     }
 
-    pub(crate) fn get_cards(&self) -> &Cards {
+    pub fn get_cards(&self) -> &Cards {
         &self.state.cards
     }
 
-    pub(crate) fn has_note(&self, s: &String) -> bool {
+    pub fn has_note(&self, s: &String) -> bool {
         self.state.notes.contains(s)
     }
 
@@ -334,7 +284,7 @@ impl SelectionPtr {
         self.state.begin_general.borrow_mut().next()
     }
 
-    pub(crate) fn get_cost_set(&self) -> &CostSet {
+    pub fn get_cost_set(&self) -> &CostSet {
         &self.state.costs_in_supply
     }
 
@@ -346,19 +296,19 @@ impl SelectionPtr {
         self.state.set_need_to_check(v, s);
     }
 
-    pub(crate) fn get_target_set(&self) -> &Vec<Box<dyn CostTarget>> {
+    pub(crate) fn get_target_set(&self) -> &Vec<Rc<dyn CostTarget>> {
         &self.state.targets
     }
 
-    pub(crate) fn get_collection(&self) -> &CardCollectionPtr {
+    pub fn get_collection(&self) -> &CardCollectionPtr {
         &self.state.card_coll
     }
 
-    pub(crate) fn get_interacts_keywords(&self) -> &BTreeMap<String, u64> {
+    pub fn get_interacts_keywords(&self) -> &BTreeMap<String, u64> {
         &self.state.interacts_keywords
     }
 
-    pub(crate) fn get_keywords(&self) -> &BTreeMap<String, u64> {
+    pub fn get_keywords(&self) -> &BTreeMap<String, u64> {
         &self.state.keywords
     }
 }